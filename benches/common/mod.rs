@@ -0,0 +1,330 @@
+//! Key sets and reference structures shared by the benchmarks.
+//!
+//! Path segments are interned as `&'static str` so every structure stores the same `Copy` node
+//! type, and the comparison measures layout rather than string cloning.
+
+#![allow(dead_code)]
+
+use flat_trie::{FlatTrie, RadixTrie, Cursor, Entry};
+
+use std::collections::BTreeMap;
+
+/// A small xorshift generator, so every run benchmarks the same keys.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    pub fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+}
+
+const TOP_DIRS: &[&str] = &[
+    "src", "tests", "docs", "assets", "vendor", "scripts", "examples", "tools"
+];
+const DIRS: &[&str] = &[
+    "core", "util", "net", "io", "parser", "render", "config", "cache", "db", "ui", "auth", "api",
+    "model", "view", "shaders", "fonts", "locale", "internal", "platform", "legacy"
+];
+const STEMS: &[&str] = &[
+    "mod", "lib", "main", "index", "types", "error", "helpers", "client", "server", "state",
+    "builder", "reader", "writer", "layout", "style", "theme", "handler", "router", "schema",
+    "migrations", "fixtures", "README", "LICENSE", "changelog"
+];
+const EXTENSIONS: &[&str] = &[".rs", ".toml", ".md", ".json", ".png", ".glsl", ".txt"];
+
+const RESOURCES: &[&str] = &[
+    "users", "posts", "comments", "orders", "items", "invoices", "teams", "projects", "files",
+    "tags", "sessions", "events"
+];
+const ACTIONS: &[&str] = &[
+    "edit", "history", "members", "settings", "export", "preview", "likes", "attachments"
+];
+
+const SYLLABLES: &[&str] = &[
+    "an", "ber", "co", "de", "ex", "fi", "gra", "ing", "ly", "mo", "ness", "pre", "qu", "re",
+    "st", "tion", "un", "ver", "wa", "zy", "al", "im", "ous", "ter", "ment"
+];
+
+/// Leaks `s`, for segments that aren't known ahead of time. Only used while generating keys.
+fn intern(s: String) -> &'static str {
+    Box::leak(s.into_boxed_str())
+}
+
+/// Source tree paths like `src/net/client/mod.rs`, split into segments.
+pub fn file_paths(count: usize) -> Vec<Vec<&'static str>> {
+    let files: Vec<&'static str> = STEMS.iter()
+        .flat_map(|stem| EXTENSIONS.iter().map(move |ext| intern(format!("{}{}", stem, ext))))
+        .collect();
+
+    let mut rng = Rng::new(0x9e37_79b9_7f4a_7c15);
+    unique(count, || {
+        let mut path = vec![rng.pick(TOP_DIRS)];
+        for _ in 0..rng.below(5) {
+            path.push(rng.pick(DIRS));
+        }
+        path.push(rng.pick(&files));
+        path
+    })
+}
+
+/// REST-style URL paths like `api/v2/users/4821/settings`, split into segments.
+pub fn url_segments(count: usize) -> Vec<Vec<&'static str>> {
+    let ids: Vec<&'static str> = (0..2000).map(|i| intern((i * 7919 % 100_000).to_string())).collect();
+
+    let mut rng = Rng::new(0x2545_f491_4f6c_dd1d);
+    unique(count, || {
+        let mut path = vec!["api", rng.pick(&["v1", "v2", "v3"])];
+        for _ in 0..rng.below(3) + 1 {
+            path.push(rng.pick(RESOURCES));
+            if rng.below(4) != 0 {
+                path.push(rng.pick(&ids));
+            }
+        }
+        if rng.below(3) == 0 {
+            path.push(rng.pick(ACTIONS));
+        }
+        path
+    })
+}
+
+/// Made-up dictionary words like `prestingly`, as bytes.
+pub fn words(count: usize) -> Vec<Vec<u8>> {
+    let mut rng = Rng::new(0x5851_f42d_4c95_7f2d);
+    unique(count, || {
+        let mut word = vec![];
+        for _ in 0..rng.below(4) + 1 {
+            word.extend_from_slice(rng.pick(SYLLABLES).as_bytes());
+        }
+        word
+    })
+}
+
+/// Generates `count` distinct keys, shortest first. `FlatTrie` only gives a node a leaf when the
+/// node is inserted, so a key has to come before any keys it's a prefix of.
+fn unique<N: Ord + Clone, F: FnMut() -> Vec<N>>(count: usize, mut gen: F) -> Vec<Vec<N>> {
+    let mut seen = BTreeMap::new();
+    let mut keys = vec![];
+    while keys.len() < count {
+        let key = gen();
+        if seen.insert(key.clone(), ()).is_none() {
+            keys.push(key);
+        }
+    }
+    keys.sort_by_key(|k| k.len());
+    keys
+}
+
+/// Every distinct prefix of `depth` nodes among `keys`.
+pub fn prefixes<N: Ord + Copy>(keys: &[Vec<N>], depth: usize) -> Vec<Vec<N>> {
+    let mut prefixes: Vec<Vec<N>> = keys.iter()
+        .filter(|k| k.len() > depth)
+        .map(|k| k[..depth].to_vec())
+        .collect();
+    prefixes.sort();
+    prefixes.dedup();
+    prefixes
+}
+
+pub fn build_flat<N: Eq + Copy>(keys: &[Vec<N>]) -> FlatTrie<N, u32> {
+    let mut trie = FlatTrie::new();
+    for (i, key) in keys.iter().enumerate() {
+        flat_insert(&mut trie, key, i as u32);
+    }
+    trie
+}
+
+/// Builds the same trie as `build_flat`, with every insert made in one `FlatTrie::batch`.
+pub fn build_flat_batch<N: Eq + Copy>(keys: &[Vec<N>]) -> FlatTrie<N, u32> {
+    let mut trie = FlatTrie::new();
+    trie.batch(|batch| for (i, key) in keys.iter().enumerate() {
+        batch.insert(key.iter().cloned(), Some(i as u32));
+    });
+    trie
+}
+
+pub fn flat_insert<N: Eq + Copy>(trie: &mut FlatTrie<N, u32>, key: &[N], leaf: u32) {
+    let mut cursor = trie.cursor_mut();
+    let (&last, init) = key.split_last().unwrap();
+    for &node in init {
+        cursor.child(node).or_insert(None).enter();
+    }
+    if let Some(l) = cursor.child(last).or_insert(Some(leaf)).leaf_mut() {
+        *l = leaf;
+    }
+}
+
+/// Calls `f` with every leaf at or below the cursor, walking the trie through `Cursor`.
+pub fn flat_walk<N, F>(cursor: &mut Cursor<N, u32, &FlatTrie<N, u32>>, f: &mut F)
+    where N: Eq + Copy,
+          F: FnMut(u32)
+{
+    if let Some(&leaf) = cursor.leaf() {
+        f(leaf);
+    }
+    let parent = *cursor;
+    for &child in parent.direct_children() {
+        cursor.child(child).unwrap_occupied().enter();
+        flat_walk(cursor, f);
+        *cursor = parent;
+    }
+}
+
+/// Calls `f` with every leaf under `prefix`, or does nothing if `prefix` isn't in the trie.
+pub fn flat_prefix<N, F>(trie: &FlatTrie<N, u32>, prefix: &[N], f: &mut F)
+    where N: Eq + Copy,
+          F: FnMut(u32)
+{
+    let mut cursor = trie.cursor();
+    for &node in prefix {
+        match cursor.child(node) {
+            Entry::Occupied(entry) => {entry.enter();},
+            Entry::Vacant(..) => return
+        }
+    }
+    flat_walk(&mut cursor, f);
+}
+
+/// Prunes the node at the end of `path`, if it exists.
+pub fn flat_prune<N: Eq + Copy>(trie: &mut FlatTrie<N, u32>, path: &[N]) {
+    let mut cursor = trie.cursor_mut();
+    let (&last, init) = path.split_last().unwrap();
+    for &node in init {
+        match cursor.child(node) {
+            Entry::Occupied(entry) => {entry.enter();},
+            Entry::Vacant(..) => return
+        }
+    }
+    if let Entry::Occupied(mut entry) = cursor.child(last) {
+        entry.prune();
+    }
+}
+
+/// Prunes the nodes at the end of every path in `sorted_paths` in one `retain` pass.
+pub fn flat_retain<N: Ord + Copy>(trie: &mut FlatTrie<N, u32>, sorted_paths: &[Vec<N>]) {
+    let mut path = vec![];
+    trie.retain(|ancestors, &node, _| {
+        path.clear();
+        path.extend(ancestors.iter().map(|&&n| n));
+        path.push(node);
+        sorted_paths.binary_search(&path).is_err()
+    });
+}
+
+pub fn build_radix<N: Eq + Copy>(keys: &[Vec<N>]) -> RadixTrie<N, u32> {
+    let mut trie = RadixTrie::new();
+    for (i, key) in keys.iter().enumerate() {
+        trie.insert(key, i as u32);
+    }
+    trie
+}
+
+/// A conventional trie, where every node owns its children through a `Box`.
+#[derive(Debug, Clone)]
+pub struct BoxTrie<N, L> {
+    leaf: Option<L>,
+    children: Vec<(N, Box<BoxTrie<N, L>>)>
+}
+
+impl<N: Eq + Copy, L> BoxTrie<N, L> {
+    pub fn new() -> BoxTrie<N, L> {
+        BoxTrie {
+            leaf: None,
+            children: vec![]
+        }
+    }
+
+    pub fn insert(&mut self, key: &[N], leaf: L) {
+        let mut node = self;
+        for &k in key {
+            let position = node.children.iter().position(|&(n, _)| n == k);
+            let index = match position {
+                Some(index) => index,
+                None => {
+                    node.children.push((k, Box::new(BoxTrie::new())));
+                    node.children.len() - 1
+                }
+            };
+            node = &mut *{node}.children[index].1;
+        }
+        node.leaf = Some(leaf);
+    }
+
+    pub fn subtree(&self, key: &[N]) -> Option<&BoxTrie<N, L>> {
+        let mut node = self;
+        for &k in key {
+            match node.children.iter().find(|&&(n, _)| n == k) {
+                Some((_, child)) => node = child,
+                None => return None
+            }
+        }
+        Some(node)
+    }
+
+    pub fn get(&self, key: &[N]) -> Option<&L> {
+        self.subtree(key).and_then(|node| node.leaf.as_ref())
+    }
+
+    pub fn walk<F: FnMut(&L)>(&self, f: &mut F) {
+        if let Some(ref leaf) = self.leaf {
+            f(leaf);
+        }
+        for (_, child) in &self.children {
+            child.walk(f);
+        }
+    }
+
+    pub fn prune(&mut self, key: &[N]) {
+        let (&last, init) = key.split_last().unwrap();
+        let mut node = self;
+        for &k in init {
+            match node.children.iter().position(|&(n, _)| n == k) {
+                Some(index) => node = &mut *{node}.children[index].1,
+                None => return
+            }
+        }
+        node.children.retain(|&(n, _)| n != last);
+    }
+}
+
+pub fn build_box<N: Eq + Copy>(keys: &[Vec<N>]) -> BoxTrie<N, u32> {
+    let mut trie = BoxTrie::new();
+    for (i, key) in keys.iter().enumerate() {
+        trie.insert(key, i as u32);
+    }
+    trie
+}
+
+pub fn build_btree<N: Ord + Copy>(keys: &[Vec<N>]) -> BTreeMap<Vec<N>, u32> {
+    keys.iter().cloned().zip(0..).collect()
+}
+
+/// Calls `f` with every value whose key starts with `prefix`.
+pub fn btree_prefix<N: Ord + Copy, F: FnMut(u32)>(map: &BTreeMap<Vec<N>, u32>, prefix: &[N], f: &mut F) {
+    for (_, &leaf) in map.range(prefix.to_vec()..).take_while(|&(k, _)| k.starts_with(prefix)) {
+        f(leaf);
+    }
+}
+
+/// Removes every key that starts with `prefix`.
+pub fn btree_prune<N: Ord + Copy>(map: &mut BTreeMap<Vec<N>, u32>, prefix: &[N]) {
+    let mut tail = map.split_off(prefix);
+    let rest_start = tail.keys().find(|k| !k.starts_with(prefix)).cloned();
+    if let Some(rest_start) = rest_start {
+        map.append(&mut tail.split_off(&rest_start));
+    }
+}
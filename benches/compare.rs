@@ -0,0 +1,128 @@
+//! Compares `FlatTrie` against a `Box`-based trie and a `BTreeMap` keyed by the full path.
+//!
+//! Run with `cargo bench --bench compare`. Memory use is measured separately by the `footprint`
+//! bench.
+
+mod common;
+
+use common::*;
+use criterion::{criterion_group, criterion_main, Criterion, BatchSize};
+
+/// Keys per set for the lookup, prefix iteration and prune benches.
+const KEYS: usize = 5_000;
+/// Keys per set for the insert benches, which build a trie from nothing on every iteration.
+const INSERT_KEYS: usize = 1_000;
+
+macro_rules! key_sets {
+    ($($name:expr => $keys:expr),*) => {
+        fn insert(c: &mut Criterion) {
+            $({
+                let keys = $keys(INSERT_KEYS);
+                let mut group = c.benchmark_group(concat!("insert/", $name));
+                group.bench_function("flat_trie", |b| b.iter(|| build_flat(&keys)));
+                group.bench_function("flat_trie_batch", |b| b.iter(|| build_flat_batch(&keys)));
+                group.bench_function("box_trie", |b| b.iter(|| build_box(&keys)));
+                group.bench_function("btree_map", |b| b.iter(|| build_btree(&keys)));
+                group.finish();
+            })*
+        }
+
+        fn lookup(c: &mut Criterion) {
+            $({
+                let keys = $keys(KEYS);
+                let flat = build_flat(&keys);
+                let boxed = build_box(&keys);
+                let btree = build_btree(&keys);
+
+                let mut group = c.benchmark_group(concat!("lookup/", $name));
+                group.bench_function("flat_trie", |b| b.iter(|| {
+                    keys.iter().filter_map(|k| flat.get(k)).count()
+                }));
+                group.bench_function("box_trie", |b| b.iter(|| {
+                    keys.iter().filter_map(|k| boxed.get(k)).count()
+                }));
+                group.bench_function("btree_map", |b| b.iter(|| {
+                    keys.iter().filter_map(|k| btree.get(k)).count()
+                }));
+                group.finish();
+            })*
+        }
+
+        fn prefix_iter(c: &mut Criterion) {
+            $({
+                let keys = $keys(KEYS);
+                let prefixes = prefixes(&keys, 2);
+                let flat = build_flat(&keys);
+                let boxed = build_box(&keys);
+                let btree = build_btree(&keys);
+
+                let mut group = c.benchmark_group(concat!("prefix_iter/", $name));
+                group.bench_function("flat_trie", |b| b.iter(|| {
+                    let mut sum = 0u64;
+                    for prefix in &prefixes {
+                        flat_prefix(&flat, prefix, &mut |l| sum += l as u64);
+                    }
+                    sum
+                }));
+                group.bench_function("box_trie", |b| b.iter(|| {
+                    let mut sum = 0u64;
+                    for prefix in &prefixes {
+                        if let Some(subtree) = boxed.subtree(prefix) {
+                            subtree.walk(&mut |&l| sum += l as u64);
+                        }
+                    }
+                    sum
+                }));
+                group.bench_function("btree_map", |b| b.iter(|| {
+                    let mut sum = 0u64;
+                    for prefix in &prefixes {
+                        btree_prefix(&btree, prefix, &mut |l| sum += l as u64);
+                    }
+                    sum
+                }));
+                group.finish();
+            })*
+        }
+
+        fn prune(c: &mut Criterion) {
+            $({
+                let keys = $keys(KEYS);
+                // Every other two-node prefix, so the pruned subtrees are spread over the trie.
+                let pruned: Vec<_> = prefixes(&keys, 2).into_iter().step_by(2).collect();
+                let flat = build_flat(&keys);
+                let boxed = build_box(&keys);
+                let btree = build_btree(&keys);
+
+                let mut group = c.benchmark_group(concat!("prune/", $name));
+                group.bench_function("flat_trie", |b| b.iter_batched_ref(|| flat.clone(), |flat| {
+                    for prefix in &pruned {
+                        flat_prune(flat, prefix);
+                    }
+                }, BatchSize::LargeInput));
+                group.bench_function("flat_trie_retain", |b| b.iter_batched_ref(|| flat.clone(), |flat| {
+                    flat_retain(flat, &pruned);
+                }, BatchSize::LargeInput));
+                group.bench_function("box_trie", |b| b.iter_batched_ref(|| boxed.clone(), |boxed| {
+                    for prefix in &pruned {
+                        boxed.prune(prefix);
+                    }
+                }, BatchSize::LargeInput));
+                group.bench_function("btree_map", |b| b.iter_batched_ref(|| btree.clone(), |btree| {
+                    for prefix in &pruned {
+                        btree_prune(btree, prefix);
+                    }
+                }, BatchSize::LargeInput));
+                group.finish();
+            })*
+        }
+    }
+}
+
+key_sets! {
+    "file_paths" => file_paths,
+    "url_segments" => url_segments,
+    "words" => words
+}
+
+criterion_group!(benches, insert, lookup, prefix_iter, prune);
+criterion_main!(benches);
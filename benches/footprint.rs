@@ -0,0 +1,71 @@
+//! Reports how many heap bytes each structure uses to hold the same key sets.
+//!
+//! Run with `cargo bench --bench footprint`. Bytes are counted by a wrapping global allocator, so
+//! they include allocator-visible slack from `Vec` growth but not allocator overhead.
+
+mod common;
+
+use common::*;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct Counting;
+
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        LIVE_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// The number of heap bytes still held by the value `build` returns.
+fn footprint<T, F: FnOnce() -> T>(build: F) -> usize {
+    let before = LIVE_BYTES.load(Ordering::Relaxed);
+    let value = build();
+    let bytes = LIVE_BYTES.load(Ordering::Relaxed) - before;
+    drop(value);
+    bytes
+}
+
+fn report<N: Ord + Copy>(name: &str, keys: &[Vec<N>]) {
+    let rows = [
+        ("flat_trie", footprint(|| build_flat(keys))),
+        ("flat_trie_shrunk", footprint(|| {
+            let mut trie = build_flat(keys);
+            trie.shrink_to_fit();
+            trie
+        })),
+        ("radix_trie", footprint(|| build_radix(keys))),
+        ("box_trie", footprint(|| build_box(keys))),
+        ("btree_map", footprint(|| build_btree(keys)))
+    ];
+    for &(structure, bytes) in &rows {
+        println!("{:<14} {:<16} {:>10} {:>10.1}", name, structure, bytes, bytes as f64 / keys.len() as f64);
+    }
+}
+
+fn main() {
+    const KEYS: usize = 5_000;
+
+    println!("{:<14} {:<16} {:>10} {:>10}", "key set", "structure", "bytes", "bytes/key");
+    report("file_paths", &file_paths(KEYS));
+    report("url_segments", &url_segments(KEYS));
+    report("words", &words(KEYS));
+}
@@ -0,0 +1,191 @@
+//! Decodes the input into a sequence of inserts, prunes and cursor moves on a `RawTrie`, and checks
+//! the trie's invariants and the cursor's position after every one.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use flat_trie::raw::{RawTrie, RawCursor};
+
+/// Hands out the input a byte at a time, and zeroes once it runs out.
+struct Input<'a>(&'a [u8]);
+
+impl<'a> Input<'a> {
+    fn byte(&mut self) -> u8 {
+        match self.0.split_first() {
+            Some((&b, rest)) => {
+                self.0 = rest;
+                b
+            },
+            None => 0
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[derive(Debug)]
+enum Op {
+    EnterChild(u8),
+    EnterParent,
+    EnterSibling(i8),
+    /// Inserts the nodes as a chain below the cursor, optionally ending in a leaf, and optionally
+    /// moves the cursor to the last inserted node.
+    Insert{ nodes: Vec<u8>, leaf: bool, enter: bool },
+    PruneCursor,
+    /// Prunes the node at the given index in `nodes`, wherever it is relative to the cursor.
+    PruneIndex(u8),
+    JumpToIndex(u8),
+    FindLeaf(u8),
+    SetLeaf(u32),
+    /// Removes the cursor's leaf. Moves the cursor to the root, since its node might be pruned.
+    RemoveLeaf,
+    /// Removes leafless subtrees. Moves the cursor to the root, like `RemoveLeaf`.
+    Compact
+}
+
+impl Op {
+    fn decode(input: &mut Input) -> Op {
+        match input.byte() % 11 {
+            0 => Op::EnterChild(input.byte()),
+            1 => Op::EnterParent,
+            2 => Op::EnterSibling(input.byte() as i8),
+            3 => {
+                let flags = input.byte();
+                // A small alphabet makes collisions with existing children likely.
+                let nodes = (0..flags % 4 + 1).map(|_| input.byte() % 8).collect();
+                Op::Insert {
+                    nodes,
+                    leaf: flags & 0x10 != 0,
+                    enter: flags & 0x20 != 0
+                }
+            },
+            4 => Op::PruneCursor,
+            5 => Op::PruneIndex(input.byte()),
+            6 => Op::JumpToIndex(input.byte()),
+            7 => Op::FindLeaf(input.byte()),
+            8 => Op::SetLeaf(input.byte() as u32),
+            9 => Op::RemoveLeaf,
+            _ => Op::Compact
+        }
+    }
+}
+
+fn apply(trie: &mut RawTrie<u8, u32>, cursor: RawCursor, op: &Op, next_leaf: &mut u32) -> RawCursor {
+    match *op {
+        Op::EnterChild(n) => {
+            let children: Vec<_> = trie.node_direct_children(cursor).collect();
+            match children.len() {
+                0 => cursor,
+                len => children[n as usize % len]
+            }
+        },
+        Op::EnterParent => trie.node_parent(cursor).unwrap_or(cursor),
+        Op::EnterSibling(dist) => trie.get_sibling(cursor, dist as isize).unwrap_or(cursor),
+        Op::Insert{ ref nodes, leaf, enter } => {
+            // Inserting a duplicate child is documented to panic, so don't.
+            let first = nodes[0];
+            if trie.node_direct_children(cursor).any(|c| *trie.get_node(c).unwrap() == first) {
+                return cursor;
+            }
+
+            let leaf_opt = match leaf {
+                true => {
+                    *next_leaf += 1;
+                    Some(*next_leaf)
+                },
+                false => None
+            };
+            let inserted = trie.insert_nodes_after(cursor, nodes.iter().cloned(), leaf_opt);
+            assert_eq!(trie.get_node(inserted), nodes.last());
+            assert_eq!(inserted.depth(), cursor.depth() + nodes.len() as isize);
+            match enter {
+                true => inserted,
+                false => trie.refresh_cursor(cursor)
+            }
+        },
+        Op::PruneCursor => prune(trie, cursor, cursor),
+        Op::PruneIndex(n) => match trie.node_count() {
+            0 => cursor,
+            count => {
+                let pruned = trie.node_index_cursor(n as usize % count);
+                prune(trie, cursor, pruned)
+            }
+        },
+        Op::JumpToIndex(n) => match trie.node_count() {
+            0 => cursor,
+            count => trie.node_index_cursor(n as usize % count)
+        },
+        Op::FindLeaf(n) => {
+            let found = trie.find_leaf_after_wrapping_by(cursor, |&l| l % 8 == n as u32 % 8);
+            if let Some(found) = found {
+                assert_eq!(trie.get_leaf(found).map(|&l| l % 8), Some(n as u32 % 8));
+            }
+            found.unwrap_or(cursor)
+        },
+        Op::SetLeaf(value) => {
+            if let Some(leaf) = trie.get_leaf_mut(cursor) {
+                *leaf = value;
+            }
+            cursor
+        },
+        Op::RemoveLeaf => {
+            let leaf_count = trie.leaf_count();
+            let expected = trie.get_leaf(cursor).cloned();
+            assert_eq!(trie.remove_leaf(cursor), expected);
+            assert_eq!(trie.leaf_count(), leaf_count - expected.is_some() as usize);
+            RawCursor::root()
+        },
+        Op::Compact => {
+            let leaf_count = trie.leaf_count();
+            trie.compact();
+            assert_eq!(trie.leaf_count(), leaf_count);
+            let nodes_with_leaves = (0..trie.node_count())
+                .filter(|&i| trie.subtree_leaf_count(trie.node_index_cursor(i)) > 0)
+                .count();
+            assert_eq!(nodes_with_leaves, trie.node_count());
+            RawCursor::root()
+        }
+    }
+}
+
+/// Prunes the node at `pruned` and returns where `cursor` ends up.
+fn prune(trie: &mut RawTrie<u8, u32>, cursor: RawCursor, pruned: RawCursor) -> RawCursor {
+    let node_count = trie.node_count();
+    let pruned_range = trie.subtree_node_range(pruned);
+    let pruned_parent = trie.node_parent(pruned).unwrap_or(RawCursor::root());
+    trie.prune_node(pruned);
+    assert_eq!(trie.node_count(), node_count - pruned_range.len());
+    trie.cursor_after_prune(cursor, pruned_range, pruned_parent)
+}
+
+fn check(trie: &RawTrie<u8, u32>, cursor: RawCursor) {
+    if let Err(e) = trie.validate() {
+        panic!("tree corrupted: {}", e);
+    }
+
+    // The cursor we've been tracking should be the same one a fresh lookup gives.
+    assert_eq!(trie.refresh_cursor(cursor), cursor);
+    match cursor == RawCursor::root() {
+        true => assert!(trie.node_parent(cursor).is_none()),
+        false => assert!(trie.get_node(cursor).is_some())
+    }
+    for child in trie.node_direct_children(cursor) {
+        assert_eq!(trie.node_parent(child), Some(cursor));
+        assert_eq!(child.depth(), cursor.depth() + 1);
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut input = Input(data);
+    let mut trie = RawTrie::new();
+    let mut cursor = RawCursor::root();
+    let mut next_leaf = 0;
+
+    while !input.is_empty() {
+        let op = Op::decode(&mut input);
+        cursor = apply(&mut trie, cursor, &op, &mut next_leaf);
+        check(&trie, cursor);
+    }
+});
@@ -0,0 +1,100 @@
+//! Decodes the input into a pre-order node listing, builds a `RawTrie` from it, then walks every
+//! node and checks that each way of navigating the trie agrees with the others.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use flat_trie::raw::{RawTrie, RawCursor, PreorderBuilder};
+
+/// Every byte pair is a node: the first byte picks its depth and whether it has a leaf, the second
+/// is the node itself.
+fn build(data: &[u8]) -> RawTrie<u8, u32> {
+    let mut builder = PreorderBuilder::new();
+    // `siblings[d]` holds the nodes already pushed under the current parent at depth `d`.
+    let mut siblings: Vec<Vec<u8>> = vec![];
+
+    for (i, pair) in data.chunks(2).enumerate() {
+        let (shape, node) = match *pair {
+            [shape, node] => (shape, node % 8),
+            _ => break
+        };
+        let depth = (shape & 0x0f) as usize % (siblings.len() + 1);
+        if siblings.get(depth).map(|s| s.contains(&node)).unwrap_or(false) {
+            continue;
+        }
+
+        siblings.truncate(depth + 1);
+        if siblings.len() == depth {
+            siblings.push(vec![]);
+        }
+        siblings[depth].push(node);
+        let node_index = builder.push_node(depth, node);
+        if shape & 0x10 != 0 {
+            builder.set_leaf(node_index, i as u32);
+        }
+    }
+
+    builder.build()
+}
+
+/// Checks `cursor` against its children and returns the number of nodes in its subtree, including
+/// itself.
+fn check_node(trie: &RawTrie<u8, u32>, cursor: RawCursor, path: &mut Vec<u8>) -> usize {
+    assert_eq!(trie.node_enter_children(RawCursor::root(), path.iter()), Ok(cursor));
+    if cursor != RawCursor::root() {
+        assert_eq!(trie.node_index_cursor(cursor.node_index() as usize), cursor);
+    }
+
+    let children: Vec<_> = trie.node_direct_children(cursor).collect();
+    let range = trie.subtree_node_range(cursor);
+    let mut subtree_nodes = 1;
+    let mut subtree_leaves = trie.get_leaf(cursor).is_some() as usize;
+
+    for (i, &child) in children.iter().enumerate() {
+        assert_eq!(trie.node_parent(child), Some(cursor));
+        assert_eq!(child.depth(), cursor.depth() + 1);
+        assert!(range.start <= child.node_index() as usize && (child.node_index() as usize) < range.end);
+
+        // Only children that start a jump know about their siblings.
+        for (j, &sibling) in children.iter().enumerate() {
+            if let Some(found) = trie.get_sibling(child, j as isize - i as isize) {
+                assert_eq!(found, sibling);
+            }
+        }
+
+        path.push(*trie.get_node(child).unwrap());
+        subtree_nodes += check_node(trie, child, path);
+        path.pop();
+        subtree_leaves += trie.subtree_leaf_count(child);
+    }
+
+    let last = trie.last_child_node(cursor);
+    match children.len() {
+        0 => assert_eq!(last, cursor),
+        _ => assert_eq!(last.node_index() as usize, range.end - 1)
+    }
+    assert_eq!(trie.subtree_leaf_count(cursor), subtree_leaves);
+    match cursor == RawCursor::root() {
+        true => assert_eq!(range.len(), subtree_nodes - 1),
+        false => assert_eq!(range.len(), subtree_nodes)
+    }
+    subtree_nodes
+}
+
+fuzz_target!(|data: &[u8]| {
+    let trie = build(data);
+    if let Err(e) = trie.validate() {
+        panic!("tree corrupted: {}", e);
+    }
+
+    let node_count = check_node(&trie, RawCursor::root(), &mut vec![]) - 1;
+    assert_eq!(node_count, trie.node_count());
+
+    for node in 0..8 {
+        let found = trie.find_nodes_by(|&n| n == node).count();
+        assert_eq!(found, trie.nodes().iter().filter(|&&n| n == node).count());
+        if let Some(cursor) = trie.find_node_after_wrapping_by(RawCursor::root(), |&n| n == node) {
+            assert_eq!(trie.get_node(cursor), Some(&node));
+        }
+    }
+});
@@ -0,0 +1,75 @@
+//! `Batch`, for making many inserts and removals with one rebuild of the trie's jumps.
+
+use super::FlatTrie;
+
+use alloc::vec::Vec;
+
+/// Inserts and removals queued up by `FlatTrie::batch`. They're applied in the order they were
+/// made, once the closure passed to `batch` returns.
+#[derive(Debug)]
+pub struct Batch<N, L> {
+    ops: Vec<BatchOp<N, L>>
+}
+
+#[derive(Debug)]
+enum BatchOp<N, L> {
+    Insert(Vec<N>, Option<L>),
+    Prune(Vec<N>)
+}
+
+impl<N: Eq, L> Batch<N, L> {
+    /// Queues inserting every missing node along `path`. New nodes go after their existing
+    /// siblings. If `leaf` is `Some`, it replaces the leaf at the end of `path`.
+    ///
+    /// # Panics
+    /// Panics if `path` is empty and `leaf` is `Some`, since the root can't have a leaf.
+    pub fn insert<I>(&mut self, path: I, leaf: Option<L>)
+        where I: IntoIterator<Item=N>
+    {
+        let path: Vec<N> = path.into_iter().collect();
+        if path.is_empty() && leaf.is_some() {
+            panic!("Attempted to give the root a leaf");
+        }
+        self.ops.push(BatchOp::Insert(path, leaf));
+    }
+
+    /// Queues removing the node at the end of `path` and all of its descendants. Does nothing if
+    /// the node doesn't exist when the removal is applied. An empty path removes every node.
+    pub fn prune<I>(&mut self, path: I)
+        where I: IntoIterator<Item=N>
+    {
+        self.ops.push(BatchOp::Prune(path.into_iter().collect()));
+    }
+
+    /// The number of queued inserts and removals.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+impl<N: Eq, L> FlatTrie<N, L> {
+    /// Calls `f` with a `Batch`, then applies everything queued on it. The trie ends up the same as
+    /// if each edit had been made on its own, but the jumps are only rebuilt once, so this is much
+    /// faster than inserting nodes one at a time through a cursor.
+    pub fn batch<F, R>(&mut self, f: F) -> R
+        where F: FnOnce(&mut Batch<N, L>) -> R
+    {
+        let mut batch = Batch { ops: Vec::new() };
+        let result = f(&mut batch);
+        if !batch.ops.is_empty() {
+            self.0.edit(|tree| {
+                for op in batch.ops {
+                    match op {
+                        BatchOp::Insert(path, leaf) => tree.insert(path, leaf),
+                        BatchOp::Prune(path) => tree.prune(&path)
+                    }
+                }
+            });
+        }
+        result
+    }
+}
@@ -0,0 +1,500 @@
+//! A versioned binary format for `FlatTrie`, and `FlatTrieView` for querying that format in place.
+//!
+//! All integers are little-endian. The data starts with a 48-byte header:
+//!
+//! | Offset | Size | Field                     |
+//! |--------|------|---------------------------|
+//! | 0      | 8    | Magic bytes, `b"FLATTRIE"` |
+//! | 8      | 4    | Format version            |
+//! | 12     | 4    | Node width, in bytes      |
+//! | 16     | 4    | Leaf width, in bytes      |
+//! | 20     | 4    | Reserved, zero            |
+//! | 24     | 8    | Number of jumps           |
+//! | 32     | 8    | Number of nodes           |
+//! | 40     | 8    | Number of leaves          |
+//!
+//! The header is followed by the jump records, the nodes and the leaves, in that order. Each jump
+//! record is 48 bytes long:
+//!
+//! | Offset | Size | Field                                       |
+//! |--------|------|---------------------------------------------|
+//! | 0      | 8    | Parent jump index, `-1` for the root jump   |
+//! | 8      | 8    | Index of the jump's first node              |
+//! | 16     | 8    | Distance to the jump's major node           |
+//! | 24     | 8    | Depth of the jump's first node              |
+//! | 32     | 8    | Leaf index, `-1` if there's no leaf         |
+//! | 40     | 8    | First child jump index, `u64::MAX` if none  |
+
+use super::FlatTrie;
+use crate::raw::{RawTrie, JumpRecord, IntegrityError};
+
+use core::{fmt, mem};
+use core::borrow::Borrow;
+use core::convert::Infallible;
+use core::marker::PhantomData;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use std::io;
+
+pub const FORMAT_VERSION: u32 = 1;
+
+const MAGIC: &[u8; 8] = b"FLATTRIE";
+const HEADER_LEN: usize = 48;
+const JUMP_RECORD_LEN: usize = 48;
+
+/// A type that's stored as a fixed number of little-endian bytes.
+pub trait FixedWidth: Sized {
+    const WIDTH: usize;
+
+    /// Writes the value into `bytes`, which is exactly `WIDTH` bytes long.
+    fn write_le(&self, bytes: &mut [u8]);
+    /// Reads a value from `bytes`, which is exactly `WIDTH` bytes long.
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_fixed_width {
+    ($($ty:ty),*) => {$(
+        impl FixedWidth for $ty {
+            const WIDTH: usize = mem::size_of::<$ty>();
+
+            #[inline]
+            fn write_le(&self, bytes: &mut [u8]) {
+                bytes.copy_from_slice(&self.to_le_bytes());
+            }
+
+            #[inline]
+            fn read_le(bytes: &[u8]) -> $ty {
+                let mut le_bytes = [0; mem::size_of::<$ty>()];
+                le_bytes.copy_from_slice(bytes);
+                <$ty>::from_le_bytes(le_bytes)
+            }
+        }
+    )*}
+}
+
+impl_fixed_width!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatError {
+    /// The data doesn't start with `b"FLATTRIE"`.
+    BadMagic,
+    UnsupportedVersion(u32),
+    /// The node or leaf width in the header doesn't match `N::WIDTH` or `L::WIDTH`.
+    WidthMismatch,
+    /// The data isn't as long as the header says it should be.
+    LengthMismatch,
+    /// The jump record at the given index points outside of the trie.
+    BadJump(usize),
+    /// The jumps don't describe a valid tree.
+    Integrity(IntegrityError)
+}
+
+/// A `FlatTrie` stored in the binary format, queried without copying it out of the byte slice.
+pub struct FlatTrieView<'a, N, L> {
+    jumps: &'a [u8],
+    nodes: &'a [u8],
+    leaves: &'a [u8],
+    num_jumps: usize,
+    num_nodes: usize,
+    num_leaves: usize,
+    _marker: PhantomData<fn() -> (N, L)>
+}
+
+pub struct ViewCursor<'a, N, L> {
+    view: FlatTrieView<'a, N, L>,
+    node_index: isize,
+    jump_index: usize,
+    depth: isize
+}
+
+impl<N: Eq + FixedWidth, L: FixedWidth> FlatTrie<N, L> {
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.binary_len());
+        let written: Result<(), Infallible> = self.write_chunks(|chunk| {
+            bytes.extend_from_slice(chunk);
+            Ok(())
+        });
+        match written {
+            Ok(()) => bytes,
+            Err(never) => match never {}
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn write_binary<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.write_chunks(|chunk| writer.write_all(chunk))
+    }
+
+    /// Passes the binary form of the trie to `write` a piece at a time, stopping at the first error.
+    fn write_chunks<E, F>(&self, mut write: F) -> Result<(), E>
+        where F: FnMut(&[u8]) -> Result<(), E>
+    {
+        let raw = &self.0;
+        let jumps = raw.jump_records();
+        let (nodes, leaves) = (raw.nodes(), raw.leaves());
+
+        let mut header = [0; HEADER_LEN];
+        header[0..8].copy_from_slice(MAGIC);
+        FORMAT_VERSION.write_le(&mut header[8..12]);
+        (N::WIDTH as u32).write_le(&mut header[12..16]);
+        (L::WIDTH as u32).write_le(&mut header[16..20]);
+        (jumps.len() as u64).write_le(&mut header[24..32]);
+        (nodes.len() as u64).write_le(&mut header[32..40]);
+        (leaves.len() as u64).write_le(&mut header[40..48]);
+        write(&header)?;
+
+        let mut record = [0; JUMP_RECORD_LEN];
+        for jump in jumps {
+            write_jump(jump, &mut record);
+            write(&record)?;
+        }
+
+        let mut node_bytes = vec![0; N::WIDTH];
+        for node in nodes {
+            node.write_le(&mut node_bytes);
+            write(&node_bytes)?;
+        }
+
+        let mut leaf_bytes = vec![0; L::WIDTH];
+        for leaf in leaves {
+            leaf.write_le(&mut leaf_bytes);
+            write(&leaf_bytes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Copies a trie out of data in the binary format.
+    pub fn from_binary(bytes: &[u8]) -> Result<FlatTrie<N, L>, FormatError> {
+        FlatTrieView::new(bytes)?.to_trie()
+    }
+
+    /// The number of bytes `write_binary` writes.
+    pub fn binary_len(&self) -> usize {
+        let raw = &self.0;
+        HEADER_LEN +
+        raw.jump_records().len() * JUMP_RECORD_LEN +
+        raw.nodes().len() * N::WIDTH +
+        raw.leaves().len() * L::WIDTH
+    }
+}
+
+impl<'a, N: 'a + FixedWidth, L: 'a + FixedWidth> FlatTrieView<'a, N, L> {
+    /// Checks the header and jump records in `bytes` and creates a view over them. The nodes and
+    /// leaves aren't read until they're queried.
+    pub fn new(bytes: &'a [u8]) -> Result<FlatTrieView<'a, N, L>, FormatError> {
+        if bytes.len() < HEADER_LEN || &bytes[0..8] != MAGIC {
+            return Err(FormatError::BadMagic);
+        }
+        let version = u32::read_le(&bytes[8..12]);
+        if version != FORMAT_VERSION {
+            return Err(FormatError::UnsupportedVersion(version));
+        }
+        if u32::read_le(&bytes[12..16]) as usize != N::WIDTH || u32::read_le(&bytes[16..20]) as usize != L::WIDTH {
+            return Err(FormatError::WidthMismatch);
+        }
+
+        let count = |range: &[u8], width: usize| {
+            let count = u64::read_le(range);
+            match count <= usize::MAX as u64 {
+                true => (count as usize).checked_mul(width).map(|len| (count as usize, len)),
+                false => None
+            }
+        };
+        let (num_jumps, jumps_len) = count(&bytes[24..32], JUMP_RECORD_LEN).ok_or(FormatError::LengthMismatch)?;
+        let (num_nodes, nodes_len) = count(&bytes[32..40], N::WIDTH).ok_or(FormatError::LengthMismatch)?;
+        let (num_leaves, leaves_len) = count(&bytes[40..48], L::WIDTH).ok_or(FormatError::LengthMismatch)?;
+
+        let body = &bytes[HEADER_LEN..];
+        let body_len = jumps_len.checked_add(nodes_len).and_then(|len| len.checked_add(leaves_len));
+        if body_len != Some(body.len()) || num_jumps == 0 {
+            return Err(FormatError::LengthMismatch);
+        }
+
+        let view = FlatTrieView {
+            jumps: &body[..jumps_len],
+            nodes: &body[jumps_len..jumps_len + nodes_len],
+            leaves: &body[jumps_len + nodes_len..],
+            num_jumps: num_jumps,
+            num_nodes: num_nodes,
+            num_leaves: num_leaves,
+            _marker: PhantomData
+        };
+
+        // Make sure every index in the jump records is in bounds, so navigating the view can't
+        // read outside of it.
+        for jump_index in 0..num_jumps {
+            let record = &view.jumps[jump_index * JUMP_RECORD_LEN..(jump_index + 1) * JUMP_RECORD_LEN];
+            let parent_jump_index = i64::read_le(&record[0..8]);
+            let jump_to_node = i64::read_le(&record[8..16]);
+            let next_major_node_dist = u64::read_le(&record[16..24]);
+            let leaf_index = i64::read_le(&record[32..40]);
+            let child_jump_index = u64::read_le(&record[40..48]);
+
+            let (parent_valid, first_node_valid) = match jump_index {
+                0 => (parent_jump_index == -1, jump_to_node == -1),
+                _ => (0 <= parent_jump_index && parent_jump_index < jump_index as i64, 0 <= jump_to_node)
+            };
+            let major_node_valid = match next_major_node_dist <= num_nodes as u64 {
+                true => jump_to_node.checked_add(next_major_node_dist as i64).is_some_and(|n| n < num_nodes as i64),
+                false => false
+            };
+            let jump_valid =
+                parent_valid && first_node_valid && major_node_valid &&
+                -1 <= leaf_index && leaf_index < num_leaves as i64 &&
+                (child_jump_index == u64::MAX || child_jump_index < num_jumps as u64);
+            if !jump_valid {
+                return Err(FormatError::BadJump(jump_index));
+            }
+        }
+
+        Ok(view)
+    }
+
+    /// The number of leaves in the trie.
+    pub fn len(&self) -> usize {
+        self.num_leaves
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.num_nodes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn cursor(&self) -> ViewCursor<'a, N, L> {
+        ViewCursor {
+            view: *self,
+            node_index: -1,
+            jump_index: 0,
+            depth: -1
+        }
+    }
+
+    /// Returns the leaf at the end of `path`, if there's a node there and it has a leaf.
+    pub fn get<'b, I, O>(&self, path: I) -> Option<L>
+        where I: IntoIterator<Item=&'b O>,
+              N: Borrow<O>,
+              O: 'b + Eq + ?Sized
+    {
+        let mut cursor = self.cursor();
+        for node in path {
+            cursor = cursor.child(node)?;
+        }
+        cursor.leaf()
+    }
+
+    /// Returns a cursor at the first leaf that matches `by`.
+    pub fn find_leaf_by<F>(&self, by: F) -> Option<ViewCursor<'a, N, L>>
+        where F: FnMut(&L) -> bool
+    {
+        self.cursor().find_leaf_after_wrapping_by(by)
+    }
+
+    /// Copies the trie out of the view, checking that it describes a valid tree.
+    pub fn to_trie(&self) -> Result<FlatTrie<N, L>, FormatError>
+        where N: Eq
+    {
+        let nodes = (0..self.num_nodes).map(|i| self.node(i)).collect();
+        let jumps = (0..self.num_jumps).map(|i| self.jump(i));
+        let leaves = (0..self.num_leaves).map(|i| self.leaf(i)).collect();
+
+        let trie = RawTrie::from_raw_parts(nodes, jumps, leaves);
+        match trie.validate() {
+            Ok(()) => Ok(FlatTrie(trie)),
+            Err(e) => Err(FormatError::Integrity(e))
+        }
+    }
+
+    fn jump(&self, jump_index: usize) -> JumpRecord {
+        read_jump(&self.jumps[jump_index * JUMP_RECORD_LEN..(jump_index + 1) * JUMP_RECORD_LEN])
+    }
+
+    fn node(&self, node_index: usize) -> N {
+        N::read_le(&self.nodes[node_index * N::WIDTH..(node_index + 1) * N::WIDTH])
+    }
+
+    fn leaf(&self, leaf_index: usize) -> L {
+        L::read_le(&self.leaves[leaf_index * L::WIDTH..(leaf_index + 1) * L::WIDTH])
+    }
+}
+
+impl<'a, N: 'a + FixedWidth, L: 'a + FixedWidth> ViewCursor<'a, N, L> {
+    pub fn at_root(&self) -> bool {
+        self.node_index == -1
+    }
+
+    pub fn depth(&self) -> isize {
+        self.depth
+    }
+
+    /// # Panics
+    /// Panics if the cursor is at the root node.
+    pub fn node(&self) -> N {
+        if self.at_root() {
+            panic!("Attempted to take node of root");
+        }
+        self.view.node(self.node_index as usize)
+    }
+
+    pub fn leaf(&self) -> Option<L> {
+        let jump = self.view.jump(self.jump_index);
+        match self.at_major_node(jump) {
+            true => jump.leaf_index.map(|i| self.view.leaf(i)),
+            false => None
+        }
+    }
+
+    pub fn direct_children(&self) -> impl 'a + Iterator<Item=ViewCursor<'a, N, L>> {
+        let view = self.view;
+        let jump = view.jump(self.jump_index);
+        let (jump_index, depth) = (self.jump_index, self.depth + 1);
+
+        let direct_child = match self.at_major_node(jump) {
+            true => None,
+            false => Some(ViewCursor {
+                view: view,
+                node_index: self.node_index + 1,
+                jump_index: jump_index,
+                depth: depth
+            })
+        };
+        let child_jump_search = match (direct_child.is_some(), jump.child_jump_index) {
+            (false, Some(child_jump_index)) => child_jump_index..view.num_jumps,
+            _ => 0..0
+        };
+
+        direct_child.into_iter().chain(
+            child_jump_search
+                .map(move |i| (i, view.jump(i)))
+                .take_while(move |&(_, j)| j.parent_jump_index == jump_index as isize)
+                .map(move |(i, j)| ViewCursor {
+                    view: view,
+                    node_index: j.jump_to_node,
+                    jump_index: i,
+                    depth: depth
+                })
+        )
+    }
+
+    pub fn child<O>(&self, node: &O) -> Option<ViewCursor<'a, N, L>>
+        where N: Borrow<O>,
+              O: Eq + ?Sized
+    {
+        self.direct_children().find(|c| c.node().borrow() == node)
+    }
+
+    pub fn find_leaf_after_wrapping_by<F>(&self, mut by: F) -> Option<ViewCursor<'a, N, L>>
+        where F: FnMut(&L) -> bool
+    {
+        let view = self.view;
+        let jump_indices = (self.jump_index + 1..view.num_jumps).chain(0..self.jump_index + 1);
+        for jump_index in jump_indices {
+            let jump = view.jump(jump_index);
+            if let Some(leaf_index) = jump.leaf_index {
+                if by(&view.leaf(leaf_index)) {
+                    return Some(ViewCursor {
+                        view: view,
+                        node_index: jump.jump_to_node + jump.next_major_node_dist as isize,
+                        jump_index: jump_index,
+                        depth: jump.depth + jump.next_major_node_dist as isize
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    #[inline]
+    fn at_major_node(&self, jump: JumpRecord) -> bool {
+        self.node_index - jump.jump_to_node == jump.next_major_node_dist as isize
+    }
+}
+
+fn write_jump(jump: JumpRecord, bytes: &mut [u8]) {
+    (jump.parent_jump_index as i64).write_le(&mut bytes[0..8]);
+    (jump.jump_to_node as i64).write_le(&mut bytes[8..16]);
+    (jump.next_major_node_dist as u64).write_le(&mut bytes[16..24]);
+    (jump.depth as i64).write_le(&mut bytes[24..32]);
+    jump.leaf_index.map(|i| i as i64).unwrap_or(-1).write_le(&mut bytes[32..40]);
+    jump.child_jump_index.map(|i| i as u64).unwrap_or(u64::MAX).write_le(&mut bytes[40..48]);
+}
+
+fn read_jump(bytes: &[u8]) -> JumpRecord {
+    let leaf_index = i64::read_le(&bytes[32..40]);
+    let child_jump_index = u64::read_le(&bytes[40..48]);
+    JumpRecord {
+        parent_jump_index: i64::read_le(&bytes[0..8]) as isize,
+        jump_to_node: i64::read_le(&bytes[8..16]) as isize,
+        next_major_node_dist: u64::read_le(&bytes[16..24]) as usize,
+        depth: i64::read_le(&bytes[24..32]) as isize,
+        leaf_index: match leaf_index {
+            -1 => None,
+            _ => Some(leaf_index as usize)
+        },
+        child_jump_index: match child_jump_index == u64::MAX {
+            true => None,
+            false => Some(child_jump_index as usize)
+        }
+    }
+}
+
+impl<'a, N, L> Clone for FlatTrieView<'a, N, L> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, N, L> Copy for FlatTrieView<'a, N, L> {}
+
+impl<'a, N, L> Clone for ViewCursor<'a, N, L> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, N, L> Copy for ViewCursor<'a, N, L> {}
+
+impl<'a, N, L> fmt::Debug for FlatTrieView<'a, N, L> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FlatTrieView")
+            .field("num_jumps", &self.num_jumps)
+            .field("num_nodes", &self.num_nodes)
+            .field("num_leaves", &self.num_leaves)
+            .finish()
+    }
+}
+
+impl<'a, N: FixedWidth + fmt::Debug, L: FixedWidth + fmt::Debug> fmt::Debug for ViewCursor<'a, N, L> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ViewCursor")
+            .field("depth", &self.depth)
+            .field("node", &match self.at_root() {
+                true => None,
+                false => Some(self.node())
+            })
+            .field("leaf", &self.leaf())
+            .finish()
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FormatError::BadMagic => write!(f, "data isn't in the flat-trie binary format"),
+            FormatError::UnsupportedVersion(version) => write!(f, "unsupported format version {}", version),
+            FormatError::WidthMismatch => write!(f, "node or leaf width doesn't match the stored width"),
+            FormatError::LengthMismatch => write!(f, "data length doesn't match the header"),
+            FormatError::BadJump(jump_index) => write!(f, "jump {} points outside of the trie", jump_index),
+            FormatError::Integrity(e) => write!(f, "jumps don't describe a valid tree: {}", e)
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FormatError {}
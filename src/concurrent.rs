@@ -0,0 +1,72 @@
+//! `ConcurrentFlatTrie`, which lets threads read published versions of a trie while another thread
+//! writes the next one.
+
+use super::{FlatTrie, Snapshot};
+
+use std::sync::{Mutex, RwLock, PoisonError};
+
+/// A `FlatTrie` shared between threads, updated read-copy-update style.
+///
+/// Readers `load` the latest published version as a `Snapshot`, which only holds a lock for as
+/// long as it takes to clone an `Arc`, so lookups and cursors never block each other or the
+/// writer. Writers take turns changing a private copy of the trie through `write`, which publishes
+/// the result when the closure returns. Readers holding an older snapshot keep seeing that version
+/// until they `load` again.
+#[derive(Debug)]
+pub struct ConcurrentFlatTrie<N: Eq, L> {
+    published: RwLock<Snapshot<N, L>>,
+    writer: Mutex<FlatTrie<N, L>>
+}
+
+impl<N: Eq + Clone, L: Clone> ConcurrentFlatTrie<N, L> {
+    /// Publishes `trie` as the first version.
+    pub fn new(mut trie: FlatTrie<N, L>) -> ConcurrentFlatTrie<N, L> {
+        ConcurrentFlatTrie {
+            published: RwLock::new(trie.snapshot()),
+            writer: Mutex::new(trie)
+        }
+    }
+
+    /// The latest published version of the trie.
+    pub fn load(&self) -> Snapshot<N, L> {
+        self.published.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Calls `f` with the writer's copy of the trie, then publishes it. Waits for any other write
+    /// to finish first.
+    ///
+    /// The first change `f` makes to each of the trie's buffers copies it, since the published
+    /// version shares them. Use `FlatTrie::batch` inside `f` to make many changes cheaply.
+    ///
+    /// # Panics
+    /// Panics if an earlier call to `f` panicked. Nothing that call changed was published.
+    pub fn write<F, R>(&self, f: F) -> R
+        where F: FnOnce(&mut FlatTrie<N, L>) -> R
+    {
+        let mut trie = self.writer.lock().expect("an earlier write panicked");
+        let result = f(&mut trie);
+        let snapshot = trie.snapshot();
+        *self.published.write().unwrap_or_else(PoisonError::into_inner) = snapshot;
+        result
+    }
+
+    /// The writer's copy of the trie, which is the same as the latest published version.
+    ///
+    /// # Panics
+    /// Panics if a call to `write` panicked.
+    pub fn into_inner(self) -> FlatTrie<N, L> {
+        self.writer.into_inner().expect("an earlier write panicked")
+    }
+}
+
+impl<N: Eq + Clone, L: Clone> Default for ConcurrentFlatTrie<N, L> {
+    fn default() -> ConcurrentFlatTrie<N, L> {
+        ConcurrentFlatTrie::new(FlatTrie::new())
+    }
+}
+
+impl<N: Eq + Clone, L: Clone> From<FlatTrie<N, L>> for ConcurrentFlatTrie<N, L> {
+    fn from(trie: FlatTrie<N, L>) -> ConcurrentFlatTrie<N, L> {
+        ConcurrentFlatTrie::new(trie)
+    }
+}
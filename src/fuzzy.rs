@@ -0,0 +1,79 @@
+//! Finding the paths in a `FlatTrie` within a Levenshtein distance of a key.
+
+use super::FlatTrie;
+use crate::raw::{Descendants, RawCursor};
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+impl<N: Eq, L> FlatTrie<N, L> {
+    /// Every leaf whose path is within `max_distance` insertions, deletions and substitutions of
+    /// `key`, along with that path and its distance from `key`, in pre-order.
+    ///
+    /// The trie is walked depth-first, computing one row of the Levenshtein table per node from
+    /// its parent's row, so paths that share a prefix share the work for it. Subtrees are skipped
+    /// once every entry in a row is over `max_distance`, since no path below can come back under.
+    pub fn fuzzy_search<'a, O>(&'a self, key: &'a [O], max_distance: usize) -> impl 'a + Iterator<Item=(Vec<&'a N>, &'a L, usize)>
+        where N: PartialEq<O>
+    {
+        FuzzySearch {
+            trie: self,
+            descendants: self.0.descendants(RawCursor::root()),
+            key: key,
+            max_distance: max_distance,
+            rows: (0..=key.len()).collect(),
+            path: vec![]
+        }
+    }
+}
+
+struct FuzzySearch<'a, N: Eq, L, O> {
+    trie: &'a FlatTrie<N, L>,
+    descendants: Descendants<'a, N, L>,
+    key: &'a [O],
+    max_distance: usize,
+    /// The rows for the path to the last node visited, one per depth starting with the root's,
+    /// each `key.len() + 1` long.
+    rows: Vec<usize>,
+    /// The path to the last node visited.
+    path: Vec<&'a N>
+}
+
+impl<'a, N, L, O> Iterator for FuzzySearch<'a, N, L, O>
+    where N: Eq + PartialEq<O>
+{
+    type Item = (Vec<&'a N>, &'a L, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let raw = &self.trie.0;
+        let width = self.key.len() + 1;
+        while let Some(cursor) = self.descendants.next() {
+            let depth = cursor.depth() as usize;
+            let node = raw.get_node(cursor).unwrap();
+            self.path.truncate(depth);
+            self.path.push(node);
+
+            let parent = depth * width;
+            self.rows.truncate(parent + width);
+            self.rows.push(self.rows[parent] + 1);
+            for j in 1..width {
+                let substitution = self.rows[parent + j - 1] + usize::from(*node != self.key[j - 1]);
+                let deletion = self.rows[parent + j] + 1;
+                let insertion = self.rows[parent + width + j - 1] + 1;
+                self.rows.push(substitution.min(deletion).min(insertion));
+            }
+
+            let row = &self.rows[parent + width..];
+            let distance = row[width - 1];
+            if row.iter().all(|&d| d > self.max_distance) {
+                self.descendants.skip_subtree();
+            }
+            if distance <= self.max_distance {
+                if let Some(leaf) = raw.get_leaf(cursor) {
+                    return Some((self.path.clone(), leaf, distance));
+                }
+            }
+        }
+        None
+    }
+}
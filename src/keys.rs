@@ -0,0 +1,170 @@
+//! `StrTrie` and `BytesTrie`, which store one `FlatTrie` node per `char` or byte of their keys.
+
+use super::FlatTrie;
+use crate::raw::{RawTrie, RawCursor};
+
+use core::iter;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Follows `key` down from the root one node at a time, so the key doesn't have to be collected
+/// first. On failure, returns the deepest node found and how many elements of `key` it took.
+pub(crate) fn find<N, L, I>(raw: &RawTrie<N, L>, key: I) -> Result<RawCursor, (RawCursor, usize)>
+    where N: Eq,
+          I: IntoIterator<Item=N>
+{
+    let mut cursor = RawCursor::root();
+    for (i, node) in key.into_iter().enumerate() {
+        cursor = raw.node_enter_children(cursor, iter::once(&node)).map_err(|(cursor, _)| (cursor, i))?;
+    }
+    Ok(cursor)
+}
+
+/// Gives the node at the end of `key` the leaf `leaf`, inserting any missing nodes, and returns
+/// the node's old leaf.
+///
+/// # Panics
+/// Panics if `key` is empty.
+pub(crate) fn insert<N, L, I>(trie: &mut FlatTrie<N, L>, key: I, leaf: L) -> Option<L>
+    where N: Eq,
+          I: IntoIterator<Item=N> + Clone
+{
+    match find(&trie.0, key.clone()) {
+        Ok(cursor) => trie.0.set_leaf(cursor, leaf),
+        Err((cursor, found)) => {
+            let missing: Vec<N> = key.into_iter().skip(found).collect();
+            trie.0.insert_nodes_after(cursor, missing, Some(leaf));
+            None
+        }
+    }
+}
+
+/// Removes the leaf at the end of `key`, along with any nodes left without leaves or other
+/// children.
+pub(crate) fn remove<N, L, I>(trie: &mut FlatTrie<N, L>, key: I) -> Option<L>
+    where N: Eq,
+          I: IntoIterator<Item=N>
+{
+    let cursor = find(&trie.0, key).ok()?;
+    trie.0.remove_leaf(cursor)
+}
+
+/// Every leaf at or below `cursor` and its key, in pre-order. `prefix` is the key of `cursor`'s
+/// node.
+pub(crate) fn entries<'a, N, L>(raw: &'a RawTrie<N, L>, cursor: RawCursor, prefix: Vec<N>) -> impl 'a + Iterator<Item=(Vec<N>, &'a L)>
+    where N: Eq + Clone
+{
+    let own = raw.get_leaf(cursor).map(|leaf| (prefix.clone(), leaf));
+    let mut key = prefix;
+    own.into_iter().chain(raw.descendants(cursor).filter_map(move |cursor| {
+        key.truncate(cursor.depth() as usize);
+        key.push(raw.get_node(cursor).unwrap().clone());
+        raw.get_leaf(cursor).map(|leaf| (key.clone(), leaf))
+    }))
+}
+
+macro_rules! key_trie {
+    (
+        $(#[$attr:meta])*
+        $name:ident<$node:ty>, $key:ty, $owned:ty,
+        |$k:ident| $elements:expr,
+        |$v:ident| $collect:expr
+    ) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone)]
+        pub struct $name<L>(FlatTrie<$node, L>);
+
+        impl<L> Default for $name<L> {
+            fn default() -> $name<L> {
+                $name::new()
+            }
+        }
+
+        impl<L> $name<L> {
+            pub fn new() -> $name<L> {
+                $name(FlatTrie::new())
+            }
+
+            /// The underlying trie, for walking it with a `Cursor`.
+            pub fn as_flat(&self) -> &FlatTrie<$node, L> {
+                &self.0
+            }
+
+            /// Gives `key` the leaf `leaf`, and returns its old leaf.
+            ///
+            /// # Panics
+            /// Panics if `key` is empty, since the root can't have a leaf.
+            pub fn insert(&mut self, key: &$key, leaf: L) -> Option<L> {
+                let $k = key;
+                insert(&mut self.0, $elements, leaf)
+            }
+
+            pub fn get(&self, key: &$key) -> Option<&L> {
+                let $k = key;
+                let raw = &self.0.0;
+                find(raw, $elements).ok().and_then(|cursor| raw.get_leaf(cursor))
+            }
+
+            pub fn get_mut(&mut self, key: &$key) -> Option<&mut L> {
+                let $k = key;
+                let cursor = find(&self.0.0, $elements).ok()?;
+                self.0.0.get_leaf_mut(cursor)
+            }
+
+            /// Removes and returns `key`'s leaf, along with any nodes that were only there for it.
+            pub fn remove(&mut self, key: &$key) -> Option<L> {
+                let $k = key;
+                remove(&mut self.0, $elements)
+            }
+
+            /// Whether any key starts with `prefix`.
+            pub fn starts_with(&self, prefix: &$key) -> bool {
+                self.iter_prefix(prefix).next().is_some()
+            }
+
+            /// Every key and its leaf. Keys are visited in pre-order, and keys that branch off at
+            /// the same point in the order they were first inserted.
+            pub fn iter(&self) -> impl '_ + Iterator<Item=($owned, &L)> {
+                entries(&self.0.0, RawCursor::root(), Vec::new()).map(|($v, leaf)| ($collect, leaf))
+            }
+
+            /// Every key, in the same order as `iter`.
+            pub fn keys(&self) -> impl '_ + Iterator<Item=$owned> {
+                self.iter().map(|(key, _)| key)
+            }
+
+            /// Every key that starts with `prefix` and its leaf, in the same order as `iter`.
+            pub fn iter_prefix<'a>(&'a self, prefix: &$key) -> impl 'a + Iterator<Item=($owned, &'a L)> {
+                let $k = prefix;
+                let raw = &self.0.0;
+                let entries = find(raw, $elements).ok()
+                    .map(|cursor| entries(raw, cursor, $elements.collect()));
+                entries.into_iter().flatten().map(|($v, leaf)| ($collect, leaf))
+            }
+
+            /// The number of keys in the trie.
+            pub fn len(&self) -> usize {
+                self.0.len()
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.0.is_empty()
+            }
+        }
+    };
+}
+
+key_trie! {
+    /// A trie keyed by strings, with one node per `char`.
+    StrTrie<char>, str, String,
+    |key| key.chars(),
+    |chars| chars.into_iter().collect()
+}
+
+key_trie! {
+    /// A trie keyed by byte strings, with one node per byte.
+    BytesTrie<u8>, [u8], Vec<u8>,
+    |key| key.iter().cloned(),
+    |bytes| bytes
+}
@@ -1,13 +1,57 @@
-#![feature(conservative_impl_trait, splice, slice_rotate, range_contains, specialization)]
-extern crate odds;
-mod raw;
-
-use raw::*;
+#![cfg_attr(not(feature = "std"), no_std)]
+// Struct literals in this crate spell out `field: field`.
+#![allow(clippy::redundant_field_names)]
 
-use std::borrow::{Borrow, BorrowMut};
-use std::marker::PhantomData;
+extern crate alloc;
 
-use std::fmt::{self, Debug, Formatter};
+#[cfg(not(feature = "fuzzing"))]
+mod raw;
+/// The underlying trie representation. Only exposed for the fuzz targets, and not covered by
+/// semver.
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod raw;
+mod binary;
+mod batch;
+mod radix;
+mod keys;
+mod pattern;
+mod fuzzy;
+mod matcher;
+mod router;
+#[cfg(feature = "std")]
+mod path;
+mod snapshot;
+#[cfg(feature = "std")]
+mod concurrent;
+#[cfg(feature = "serde")]
+mod serde_impl;
+#[cfg(feature = "rayon")]
+mod par;
+
+use crate::raw::*;
+pub use raw::{IntegrityError, MemoryUsage};
+pub use batch::Batch;
+pub use radix::RadixTrie;
+pub use keys::{StrTrie, BytesTrie};
+pub use pattern::Segment;
+pub use matcher::Matcher;
+pub use router::{Router, RouteSegment, Params};
+#[cfg(feature = "std")]
+pub use path::PathTrie;
+pub use snapshot::Snapshot;
+#[cfg(feature = "std")]
+pub use concurrent::ConcurrentFlatTrie;
+pub use binary::{FixedWidth, FlatTrieView, ViewCursor, FormatError, FORMAT_VERSION};
+#[cfg(feature = "serde")]
+pub use serde_impl::compact;
+
+use core::borrow::{Borrow, BorrowMut};
+use alloc::borrow::ToOwned;
+use core::marker::PhantomData;
+use core::convert::Infallible;
+
+use core::fmt::{self, Debug, Formatter};
 
 // fn main() {
 //     let mut tree: FlatTrie<_, i32> = FlatTrie(RawTrie::new());
@@ -52,7 +96,7 @@ use std::fmt::{self, Debug, Formatter};
 //     }
 // }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FlatTrie<N: Eq, L>(RawTrie<N, L>);
 
 #[derive(Clone, Copy)]
@@ -110,11 +154,46 @@ pub enum EnterParentError {
     AtRoot
 }
 
+impl<N: Eq, L> Default for FlatTrie<N, L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<N: Eq, L> FlatTrie<N, L> {
     pub fn new() -> FlatTrie<N, L> {
         FlatTrie(RawTrie::new())
     }
 
+    /// Creates an empty trie with room for at least `nodes` nodes, `jumps` jumps and `leaves`
+    /// leaves before it reallocates.
+    ///
+    /// A jump starts each run of nodes that doesn't branch or have leaves, and the root always
+    /// takes one, so a trie never needs more than `nodes + 1` jumps.
+    pub fn with_capacity(nodes: usize, jumps: usize, leaves: usize) -> FlatTrie<N, L> {
+        FlatTrie(RawTrie::with_capacity(nodes, jumps, leaves))
+    }
+
+    /// Reserves room for at least `nodes` more nodes, `jumps` more jumps and `leaves` more leaves.
+    pub fn reserve(&mut self, nodes: usize, jumps: usize, leaves: usize) {
+        self.0.reserve(nodes, jumps, leaves)
+    }
+
+    /// Shrinks the node, jump and leaf buffers to fit their contents.
+    pub fn shrink_to_fit(&mut self) {
+        self.0.shrink_to_fit()
+    }
+
+    /// Removes every node and leaf, keeping the allocated capacity unless a `Snapshot` shares it.
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+
+    /// The heap memory held by the trie's buffers.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.0.memory_usage()
+    }
+
     pub fn cursor(&self) -> Cursor<N, L, &Self> {
         Cursor {
             tree: self,
@@ -138,6 +217,131 @@ impl<N: Eq, L> FlatTrie<N, L> {
             _marker: PhantomData
         }
     }
+
+    /// Returns the leaf at the end of `path`, if there's a node there and it has a leaf.
+    pub fn get<'b, I, O>(&self, path: I) -> Option<&L>
+        where I: IntoIterator<Item=&'b O>,
+              N: Borrow<O>,
+              O: 'b + Eq + ?Sized
+    {
+        let tree = &self.0;
+        tree.node_enter_children(RawCursor::root(), path).ok().and_then(|raw| tree.get_leaf(raw))
+    }
+
+    /// Removes and returns the leaf at the end of `path`, the way a delete from a classic trie
+    /// does: if that leaves the node, or a chain of its ancestors, without any leaves or other
+    /// children, they're pruned too.
+    pub fn remove<'b, I, O>(&mut self, path: I) -> Option<L>
+        where I: IntoIterator<Item=&'b O>,
+              N: Borrow<O>,
+              O: 'b + Eq + ?Sized
+    {
+        let raw = self.0.node_enter_children(RawCursor::root(), path).ok()?;
+        self.0.remove_leaf(raw)
+    }
+
+    /// Removes every subtree that doesn't have any leaves, such as chains left behind after leaves
+    /// were removed with `retain_leaves`.
+    pub fn compact(&mut self) {
+        self.0.compact()
+    }
+
+    /// The number of leaves in the trie.
+    pub fn len(&self) -> usize {
+        self.0.leaf_count()
+    }
+
+    /// The number of nodes in the trie, including nodes that don't have a leaf.
+    pub fn node_count(&self) -> usize {
+        self.0.node_count()
+    }
+
+    /// Returns `true` if the trie contains no leaves.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of nodes on the longest path from the root. An empty trie has a height of `0`.
+    pub fn height(&self) -> usize {
+        self.0.height()
+    }
+
+    /// Checks that the trie's internal layout is consistent. This is useful for tries that have
+    /// been loaded from untrusted data, as operations on an inconsistent trie may panic.
+    pub fn validate(&self) -> Result<(), IntegrityError> {
+        self.0.validate()
+    }
+
+    /// Returns a cursor at every node in the trie that matches `by`, in the order the nodes are
+    /// stored.
+    pub fn find_nodes<'a, F>(&'a self, by: F) -> impl 'a + Iterator<Item=Cursor<N, L, &'a Self>>
+        where F: 'a + FnMut(&N) -> bool
+    {
+        self.0.find_nodes_by(by).map(move |raw| Cursor {
+            tree: self,
+            raw: raw,
+            _marker: PhantomData
+        })
+    }
+
+    /// Removes every node `f` returns `false` for, along with its subtree. This takes one pass over
+    /// the trie no matter how many subtrees are removed, unlike pruning them one at a time.
+    ///
+    /// `f` is called in pre-order with the node's ancestors, starting from the root's child, then
+    /// the node itself and its leaf. It isn't called for nodes inside subtrees already removed.
+    pub fn retain<F>(&mut self, f: F)
+        where F: FnMut(&[&N], &N, Option<&L>) -> bool
+    {
+        self.0.retain(f)
+    }
+
+    /// Removes every leaf `f` returns `false` for, in one pass. Nodes are kept even if they're left
+    /// without any leaves below them.
+    pub fn retain_leaves<F>(&mut self, f: F)
+        where F: FnMut(&L) -> bool
+    {
+        self.0.retain_leaves(f)
+    }
+
+    /// Maps every node and leaf into a new trie with the same shape. Only the node and leaf
+    /// buffers are rebuilt; the jumps are reused as they are.
+    ///
+    /// # Panics
+    /// Panics if `map_node` maps two siblings to equal nodes.
+    pub fn map<N2, L2, F, G>(self, mut map_node: F, mut map_leaf: G) -> FlatTrie<N2, L2>
+        where N2: Eq,
+              F: FnMut(&N) -> N2,
+              G: FnMut(L) -> L2
+    {
+        let mapped = self.try_map(
+            |node| Ok::<_, Infallible>(map_node(node)),
+            |leaf| Ok(map_leaf(leaf))
+        );
+        match mapped {
+            Ok(trie) => trie,
+            Err(never) => match never {}
+        }
+    }
+
+    /// Like `map`, but stops at and returns the first error either function returns.
+    ///
+    /// # Panics
+    /// Panics if `map_node` maps two siblings to equal nodes.
+    pub fn try_map<N2, L2, E, F, G>(self, map_node: F, map_leaf: G) -> Result<FlatTrie<N2, L2>, E>
+        where N2: Eq,
+              F: FnMut(&N) -> Result<N2, E>,
+              G: FnMut(L) -> Result<L2, E>
+    {
+        self.0.try_map(map_node, map_leaf).map(FlatTrie)
+    }
+
+    /// Maps every leaf, removing the leaves `f` returns `None` for. Subtrees left without any
+    /// leaves are removed too, including ones that had none to begin with.
+    pub fn filter_map_leaves<L2, F>(self, f: F) -> FlatTrie<N, L2>
+        where F: FnMut(L) -> Option<L2>
+    {
+        FlatTrie(self.0.filter_map_leaves(f))
+    }
 }
 
 impl<N, L, T> Cursor<N, L, T>
@@ -166,12 +370,23 @@ impl<N, L, T> Cursor<N, L, T>
     //     self.tree.borrow().0.get_leaf_mut(self.raw)
     // }
 
+    /// The number of leaves at or below the cursor.
+    pub fn subtree_len(&self) -> usize {
+        self.tree.borrow().0.subtree_leaf_count(self.raw)
+    }
+
+    /// The number of nodes at or below the cursor. If the cursor is at the root, this is the
+    /// number of nodes in the trie.
+    pub fn subtree_node_count(&self) -> usize {
+        self.tree.borrow().0.subtree_node_range(self.raw).len()
+    }
+
     pub fn direct_children<'b>(&'b self) -> impl 'b + Iterator<Item=&'b N> {
         let tree = &self.tree.borrow().0;
         tree.node_direct_children(self.raw).map(move |rc| tree.get_node(rc).unwrap())
     }
 
-    pub fn sibling(&mut self, sibling_dist: isize) -> Entry<N, (), L, T> {
+    pub fn sibling(&mut self, sibling_dist: isize) -> Entry<'_, N, (), L, T> {
         match self.tree.borrow().0.get_sibling(self.raw, sibling_dist) {
             Some(sibling) => Entry::Occupied(OccupiedEntry {
                 cursor: self,
@@ -185,7 +400,7 @@ impl<N, L, T> Cursor<N, L, T>
         }
     }
 
-    pub fn child<O>(&mut self, node: O) -> Entry<N, O, L, T>
+    pub fn child<O>(&mut self, node: O) -> Entry<'_, N, O, L, T>
         where N: PartialEq<O>
     {
         let child = {
@@ -205,6 +420,29 @@ impl<N, L, T> Cursor<N, L, T>
         }
     }
 
+    /// Like `child`, but enters the first child that `by` returns `true` for, so children can be
+    /// matched by something other than equality. A vacant entry can still insert a child with
+    /// `insert_node`.
+    pub fn child_by<F>(&mut self, mut by: F) -> Entry<'_, N, (), L, T>
+        where F: FnMut(&N) -> bool
+    {
+        let child = {
+            let tree = &self.tree.borrow().0;
+            tree.node_direct_children(self.raw).find(|rc| by(tree.get_node(*rc).unwrap()))
+        };
+        match child {
+            Some(child) => Entry::Occupied(OccupiedEntry {
+                cursor: self,
+                move_to: child
+            }),
+            None => Entry::Vacant(VacantEntry {
+                insert_after: self.raw,
+                node: (),
+                cursor: self
+            })
+        }
+    }
+
     // pub fn child_through<I, O>(&mut self, nodes: I) -> Entry<N, O, L, T>
     //     where I: IntoIterator<Item=&'b O>,
     //           N: Borrow<O>,
@@ -224,7 +462,7 @@ impl<N, L, T> Cursor<N, L, T>
     //     }
     // }
 
-    pub fn parent(&mut self) -> OccupiedEntry<N, L, T> {
+    pub fn parent(&mut self) -> OccupiedEntry<'_, N, L, T> {
         let parent = self.tree.borrow().0.node_parent(self.raw).expect("Attempted to take parent of root");
         OccupiedEntry {
             cursor: self,
@@ -232,13 +470,13 @@ impl<N, L, T> Cursor<N, L, T>
         }
     }
 
-    pub fn find_leaf_after_wrapping<'a, M>(&'a mut self, leaf: M) -> Result<OccupiedEntry<'a, N, L, T>, &mut Self>
+    pub fn find_leaf_after_wrapping<'a, M>(&'a mut self, leaf: M) -> Result<OccupiedEntry<'a, N, L, T>, &'a mut Self>
         where L: PartialEq<M>
     {
         self.find_leaf_after_wrapping_by(|l| *l == leaf)
     }
 
-    pub fn find_leaf_after_wrapping_by<'a, F>(&'a mut self, by: F) -> Result<OccupiedEntry<'a, N, L, T>, &mut Self>
+    pub fn find_leaf_after_wrapping_by<'a, F>(&'a mut self, by: F) -> Result<OccupiedEntry<'a, N, L, T>, &'a mut Self>
         where F: FnMut(&L) -> bool
     {
         let cursor_opt = self.tree.borrow().0.find_leaf_after_wrapping_by(self.raw, by);
@@ -254,11 +492,46 @@ impl<N, L, T> Cursor<N, L, T>
         }
     }
 
+    pub fn find_node_after_wrapping<'a, M>(&'a mut self, node: M) -> Result<OccupiedEntry<'a, N, L, T>, &'a mut Self>
+        where N: PartialEq<M>
+    {
+        self.find_node_after_wrapping_by(|n| *n == node)
+    }
+
+    pub fn find_node_after_wrapping_by<'a, F>(&'a mut self, by: F) -> Result<OccupiedEntry<'a, N, L, T>, &'a mut Self>
+        where F: FnMut(&N) -> bool
+    {
+        let cursor_opt = self.tree.borrow().0.find_node_after_wrapping_by(self.raw, by);
+        match cursor_opt {
+            Some(raw) => {
+                self.raw = raw;
+                Ok(OccupiedEntry {
+                    cursor: self,
+                    move_to: raw
+                })
+            },
+            None => Err(self)
+        }
+    }
+
     pub fn to_tree(self) -> T {
         self.tree
     }
 }
 
+impl<N, L, T> Cursor<N, L, T>
+    where N: Eq,
+          T: BorrowMut<FlatTrie<N, L>>
+{
+    /// Inserts `node` as the last child of `insert_after`, keeping the cursor at the same node.
+    fn insert_child(&mut self, insert_after: RawCursor, node: N, leaf: Option<L>) -> RawCursor {
+        let tree = &mut self.tree.borrow_mut().0;
+        let insert_cursor = tree.insert_nodes_after(insert_after, Some(node), leaf);
+        self.raw = tree.refresh_cursor(self.raw);
+        insert_cursor
+    }
+}
+
 impl<'a, N, L, T> Entry<'a, N, N, L, T>
     where N: Eq,
           T: BorrowMut<FlatTrie<N, L>>
@@ -330,8 +603,16 @@ impl<'a, N, L, T> OccupiedEntry<'a, N, L, T>
         self.cursor.tree.borrow_mut().0.get_leaf_mut(self.move_to)
     }
 
+    /// Removes the entry's node and all of its descendants. If the cursor was inside the pruned
+    /// subtree, it's moved to the pruned node's parent.
     pub fn prune(&mut self) {
-        self.cursor.tree.borrow_mut().0.prune_node(self.move_to);
+        let cursor = &mut *self.cursor;
+        let tree = &mut cursor.tree.borrow_mut().0;
+        let pruned_range = tree.subtree_node_range(self.move_to);
+        let pruned_parent = tree.node_parent(self.move_to).unwrap_or(RawCursor::root());
+
+        tree.prune_node(self.move_to);
+        cursor.raw = tree.cursor_after_prune(cursor.raw, pruned_range, pruned_parent);
     }
 }
 
@@ -340,7 +621,7 @@ impl<'a, N, L, T> VacantEntry<'a, N, N, L, T>
           T: BorrowMut<FlatTrie<N, L>>
 {
     pub fn insert(self, leaf: Option<L>) -> OccupiedEntry<'a, N, L, T> {
-        let insert_cursor = self.cursor.tree.borrow_mut().0.insert_nodes_after(self.insert_after, Some(self.node), leaf);
+        let insert_cursor = self.cursor.insert_child(self.insert_after, self.node, leaf);
         OccupiedEntry {
             cursor: self.cursor,
             move_to: insert_cursor
@@ -354,7 +635,7 @@ impl<'a, N, O, L, T> VacantEntry<'a, N, O, L, T>
           T: BorrowMut<FlatTrie<N, L>>
 {
     pub fn insert_cloned(self, leaf: Option<L>) -> OccupiedEntry<'a, N, L, T> {
-        let insert_cursor = self.cursor.tree.borrow_mut().0.insert_nodes_after(self.insert_after, Some(self.node.to_owned() ), leaf);
+        let insert_cursor = self.cursor.insert_child(self.insert_after, self.node.to_owned(), leaf);
         OccupiedEntry {
             cursor: self.cursor,
             move_to: insert_cursor
@@ -367,7 +648,7 @@ impl<'a, N, O, L, T> VacantEntry<'a, N, O, L, T>
           T: BorrowMut<FlatTrie<N, L>>
 {
     pub fn insert_node(self, node: N, leaf: Option<L>) -> OccupiedEntry<'a, N, L, T> {
-        let insert_cursor = self.cursor.tree.borrow_mut().0.insert_nodes_after(self.insert_after, Some(node), leaf);
+        let insert_cursor = self.cursor.insert_child(self.insert_after, node, leaf);
         OccupiedEntry {
             cursor: self.cursor,
             move_to: insert_cursor
@@ -0,0 +1,127 @@
+//! `Matcher`, which finds every occurrence of a `FlatTrie`'s paths in a text in one pass, the way
+//! Aho-Corasick does.
+
+use super::FlatTrie;
+use crate::raw::{RawTrie, RawCursor};
+
+use alloc::vec;
+use alloc::vec::Vec;
+use alloc::collections::VecDeque;
+
+/// A `FlatTrie` compiled for finding every occurrence of its paths in a text, like a trie of byte
+/// strings in a byte text. Made with `FlatTrie::matcher`.
+///
+/// The goto transitions are the trie's own children, found through its jumps. The matcher adds a
+/// failure link to each node, to the longest proper suffix of its path that's also a path in the
+/// trie, and an output link, to the longest proper suffix that has a leaf. Scanning a text then
+/// takes one step per element plus one per failure link followed, however many paths there are.
+#[derive(Debug, Clone)]
+pub struct Matcher<'a, N: Eq, L> {
+    raw: &'a RawTrie<N, L>,
+    /// The cursor at each state's node. State `0` is the root, and state `i + 1` is the node at
+    /// index `i`.
+    cursors: Vec<RawCursor>,
+    /// The failure link of each state.
+    fail: Vec<usize>,
+    /// The output link of each state, or `0` if no proper suffix has a leaf.
+    output: Vec<usize>
+}
+
+impl<N: Eq, L> FlatTrie<N, L> {
+    /// Computes the failure links for finding the trie's paths in a text with `Matcher::scan`.
+    /// Nodes without leaves are followed like any other, but only nodes with leaves are reported.
+    pub fn matcher(&self) -> Matcher<'_, N, L> {
+        let raw = &self.0;
+        let states = raw.node_count() + 1;
+        let mut matcher = Matcher {
+            raw: raw,
+            cursors: vec![RawCursor::root(); states],
+            fail: vec![0; states],
+            output: vec![0; states]
+        };
+
+        // Breadth first, so the failure links of every shorter path are known before they're
+        // needed.
+        let mut queue = VecDeque::new();
+        queue.push_back(0);
+        while let Some(state) = queue.pop_front() {
+            for child in raw.node_direct_children(matcher.cursors[state]) {
+                let child_state = child.node_index() as usize + 1;
+                matcher.cursors[child_state] = child;
+                let fail = match state {
+                    0 => 0,
+                    _ => matcher.next_state(matcher.fail[state], raw.get_node(child).unwrap())
+                };
+                matcher.fail[child_state] = fail;
+                matcher.output[child_state] = match raw.get_leaf(matcher.cursors[fail]).is_some() {
+                    true => fail,
+                    false => matcher.output[fail]
+                };
+                queue.push_back(child_state);
+            }
+        }
+        matcher
+    }
+}
+
+impl<'a, N: Eq, L> Matcher<'a, N, L> {
+    /// Every occurrence of a path with a leaf in `text`, as the index in `text` where it starts and
+    /// the leaf. Occurrences are found in the order they end, and longer ones first when they end
+    /// at the same place. Overlapping occurrences are all found.
+    pub fn scan<'s>(&'s self, text: &'s [N]) -> impl 's + Iterator<Item=(usize, &'a L)> {
+        Scan {
+            matcher: self,
+            text: text,
+            position: 0,
+            state: 0,
+            pending: 0
+        }
+    }
+
+    /// The state reached by reading `node` in `state`, following failure links until a state has a
+    /// child equal to `node` or the root is reached.
+    fn next_state(&self, mut state: usize, node: &N) -> usize {
+        loop {
+            let raw = self.raw;
+            let child = raw.node_direct_children(self.cursors[state]).find(|&c| raw.get_node(c).unwrap() == node);
+            match (child, state) {
+                (Some(child), _) => return child.node_index() as usize + 1,
+                (None, 0) => return 0,
+                (None, _) => state = self.fail[state]
+            }
+        }
+    }
+}
+
+struct Scan<'s, 'a, N: Eq, L> {
+    matcher: &'s Matcher<'a, N, L>,
+    text: &'s [N],
+    /// The index in `text` after the last element read.
+    position: usize,
+    state: usize,
+    /// The next state on the current state's output chain to report, or `0` once it's done.
+    pending: usize
+}
+
+impl<'s, 'a, N: Eq, L> Iterator for Scan<'s, 'a, N, L> {
+    type Item = (usize, &'a L);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let matcher = self.matcher;
+        loop {
+            while self.pending != 0 {
+                let state = self.pending;
+                self.pending = matcher.output[state];
+                let cursor = matcher.cursors[state];
+                if let Some(leaf) = matcher.raw.get_leaf(cursor) {
+                    return Some((self.position - cursor.depth() as usize - 1, leaf));
+                }
+            }
+
+            let node = self.text.get(self.position)?;
+            self.state = matcher.next_state(self.state, node);
+            self.position += 1;
+            self.pending = self.state;
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Parallel iteration over a `FlatTrie`'s leaves with `rayon`.
+//!
+//! Leaves live in one contiguous `Vec`, so work that only touches leaves splits that `Vec` directly,
+//! and the nodes and jumps are copied unchanged when mapping leaves.
+
+use super::{FlatTrie, Cursor};
+
+use core::marker::PhantomData;
+
+use alloc::vec::Vec;
+
+use rayon::prelude::*;
+
+impl<N, L> FlatTrie<N, L>
+    where N: Eq + Send + Sync,
+          L: Send + Sync
+{
+    /// Visits every leaf in parallel, along with a cursor at its node. Leaves aren't visited in any
+    /// particular order.
+    pub fn par_iter(&self) -> impl '_ + ParallelIterator<Item=(Cursor<N, L, &Self>, &L)> {
+        (0..self.0.jump_count()).into_par_iter()
+            .filter_map(move |jump_index| self.0.jump_leaf(jump_index))
+            .map(move |(raw, leaf)| {
+                let cursor = Cursor {
+                    tree: self,
+                    raw: raw,
+                    _marker: PhantomData
+                };
+                (cursor, leaf)
+            })
+    }
+
+    /// Visits every leaf in parallel. Indexed in the order the leaves are stored, which doesn't follow
+    /// a traversal of the trie.
+    pub fn par_leaves_mut(&mut self) -> impl '_ + IndexedParallelIterator<Item=&mut L> {
+        self.0.leaves_mut().par_iter_mut()
+    }
+
+    /// Maps every leaf in parallel, and returns a trie with the same nodes holding the results.
+    pub fn map_leaves<M, F>(&self, f: F) -> FlatTrie<N, M>
+        where N: Clone,
+              M: Send,
+              F: Fn(&L) -> M + Sync + Send
+    {
+        let leaves: Vec<M> = self.0.leaves().par_iter().map(f).collect();
+        FlatTrie(self.0.with_parts(self.0.nodes().to_vec(), leaves))
+    }
+}
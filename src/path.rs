@@ -0,0 +1,141 @@
+//! `PathTrie`, which stores one `FlatTrie` node per path segment.
+
+use super::FlatTrie;
+use crate::keys;
+use crate::raw::RawCursor;
+
+use core::iter;
+
+use std::path::{Component, Path, PathBuf};
+
+/// A trie keyed by paths, like `src/raw.rs` or `/api/users/list`, with one node per segment.
+///
+/// Paths are normalized lexically: the root, `.` segments and repeated separators are ignored,
+/// and `..` removes the segment before it, or does nothing at the root. Symbolic links aren't
+/// resolved, so `a/link/..` is always the same as `a`. Anything that implements `AsRef<Path>`
+/// can be used as a path, including `/`-separated `&str`s.
+///
+/// Lookups walk the trie segment by segment, comparing through `Borrow<str>`, so they don't
+/// allocate.
+#[derive(Debug, Clone)]
+pub struct PathTrie<L>(FlatTrie<Box<str>, L>);
+
+impl<L> Default for PathTrie<L> {
+    fn default() -> PathTrie<L> {
+        PathTrie::new()
+    }
+}
+
+impl<L> PathTrie<L> {
+    pub fn new() -> PathTrie<L> {
+        PathTrie(FlatTrie::new())
+    }
+
+    /// The underlying trie, for walking it with a `Cursor`.
+    pub fn as_flat(&self) -> &FlatTrie<Box<str>, L> {
+        &self.0
+    }
+
+    /// Gives `path` the leaf `leaf`, and returns its old leaf.
+    ///
+    /// # Panics
+    /// Panics if `path` normalizes to the root, since the root can't have a leaf, or if a segment
+    /// isn't valid UTF-8.
+    pub fn insert<P: AsRef<Path>>(&mut self, path: P, leaf: L) -> Option<L> {
+        let segments = normalize(path.as_ref());
+        keys::insert(&mut self.0, segments.iter().map(|&s| Box::from(s)), leaf)
+    }
+
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&L> {
+        self.find(path.as_ref()).and_then(|cursor| self.0.0.get_leaf(cursor))
+    }
+
+    pub fn get_mut<P: AsRef<Path>>(&mut self, path: P) -> Option<&mut L> {
+        let cursor = self.find(path.as_ref())?;
+        self.0.0.get_leaf_mut(cursor)
+    }
+
+    /// Removes and returns `path`'s leaf, along with any segments that were only there for it.
+    pub fn remove<P: AsRef<Path>>(&mut self, path: P) -> Option<L> {
+        let cursor = self.find(path.as_ref())?;
+        self.0.0.remove_leaf(cursor)
+    }
+
+    /// Every path and its leaf, in pre-order. Paths are relative, without a leading `/`.
+    pub fn iter(&self) -> impl '_ + Iterator<Item=(PathBuf, &L)> {
+        keys::entries(&self.0.0, RawCursor::root(), vec![]).map(|(segments, leaf)| (to_path_buf(&segments), leaf))
+    }
+
+    /// Every path at or below `prefix` and its leaf, in the same order as `iter`.
+    pub fn iter_prefix<P: AsRef<Path>>(&self, prefix: P) -> impl '_ + Iterator<Item=(PathBuf, &L)> {
+        let raw = &self.0.0;
+        let entries = self.find(prefix.as_ref()).map(|cursor| {
+            let segments = normalize(prefix.as_ref()).into_iter().map(Box::from).collect();
+            keys::entries(raw, cursor, segments)
+        });
+        entries.into_iter().flatten().map(|(segments, leaf)| (to_path_buf(&segments), leaf))
+    }
+
+    /// The number of paths in the trie.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Finds `path`'s node, normalizing the path as it goes.
+    fn find(&self, path: &Path) -> Option<RawCursor> {
+        let raw = &self.0.0;
+        let mut cursor = RawCursor::root();
+        // Segments below a missing one can't be found either, but `..` can climb back out of them.
+        let mut missing = 0;
+        for component in path.components() {
+            match component {
+                Component::Prefix(..) |
+                Component::RootDir |
+                Component::CurDir => (),
+                Component::ParentDir => match missing {
+                    0 => cursor = raw.node_parent(cursor).unwrap_or(cursor),
+                    _ => missing -= 1
+                },
+                Component::Normal(segment) => {
+                    let child = segment.to_str()
+                        .filter(|_| missing == 0)
+                        .and_then(|segment| raw.node_enter_children(cursor, iter::once(segment)).ok());
+                    match child {
+                        Some(child) => cursor = child,
+                        None => missing += 1
+                    }
+                }
+            }
+        }
+        match missing {
+            0 => Some(cursor),
+            _ => None
+        }
+    }
+}
+
+/// Splits `path` into its segments, applying `.` and `..`.
+///
+/// # Panics
+/// Panics if a segment isn't valid UTF-8.
+fn normalize(path: &Path) -> Vec<&str> {
+    let mut segments = vec![];
+    for component in path.components() {
+        match component {
+            Component::Prefix(..) |
+            Component::RootDir |
+            Component::CurDir => (),
+            Component::ParentDir => {segments.pop();},
+            Component::Normal(segment) => segments.push(segment.to_str().expect("path segment isn't valid UTF-8"))
+        }
+    }
+    segments
+}
+
+fn to_path_buf(segments: &[Box<str>]) -> PathBuf {
+    segments.iter().map(|segment| &**segment).collect()
+}
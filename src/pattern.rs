@@ -0,0 +1,103 @@
+//! Matching a `FlatTrie`'s paths against patterns with wildcards, like `a/*/c` or `a/**`.
+
+use super::FlatTrie;
+use crate::raw::{RawTrie, RawCursor};
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// One segment of a pattern for `FlatTrie::matches`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Segment<O> {
+    /// Matches a node equal to the given value.
+    Exact(O),
+    /// Matches any one node, like `*` in a glob.
+    Any,
+    /// Matches any number of nodes, including none, like `**` in a glob. `a/**` matches `a` too.
+    AnyDepth
+}
+
+impl<N: Eq, L> FlatTrie<N, L> {
+    /// Every leaf whose path matches `pattern`, along with that path, in pre-order.
+    ///
+    /// Every branch that could still match is followed at once, keeping track of how far along
+    /// `pattern` each of them is, so each node is visited at most once and subtrees that can't
+    /// match are skipped. A path that matches in several ways, like `a/b/c` against `**/*/**`, is
+    /// only returned once.
+    pub fn matches<'a, O>(&'a self, pattern: &'a [Segment<O>]) -> impl 'a + Iterator<Item=(Vec<&'a N>, &'a L)>
+        where N: PartialEq<O>
+    {
+        let mut states = vec![];
+        add_state(pattern, &mut states, 0);
+        Matches {
+            raw: &self.0,
+            pattern: pattern,
+            stack: vec![(RawCursor::root(), states)],
+            path: vec![]
+        }
+    }
+}
+
+/// Adds `position` in `pattern` to `states`, along with the positions after any `AnyDepth`s it's
+/// at, since they can match nothing.
+fn add_state<O>(pattern: &[Segment<O>], states: &mut Vec<usize>, mut position: usize) {
+    while !states.contains(&position) {
+        states.push(position);
+        match pattern.get(position) {
+            Some(Segment::AnyDepth) => position += 1,
+            _ => return
+        }
+    }
+}
+
+struct Matches<'a, N: Eq, L, O> {
+    raw: &'a RawTrie<N, L>,
+    pattern: &'a [Segment<O>],
+    /// Nodes still to visit, with how far along `pattern` each way of reaching them is.
+    stack: Vec<(RawCursor, Vec<usize>)>,
+    /// The path to the last node visited.
+    path: Vec<&'a N>
+}
+
+impl<'a, N, L, O> Iterator for Matches<'a, N, L, O>
+    where N: Eq + PartialEq<O>
+{
+    type Item = (Vec<&'a N>, &'a L);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let raw = self.raw;
+        let pattern = self.pattern;
+        while let Some((cursor, states)) = self.stack.pop() {
+            if cursor != RawCursor::root() {
+                self.path.truncate(cursor.depth() as usize);
+                self.path.push(raw.get_node(cursor).unwrap());
+            }
+
+            let first_child = self.stack.len();
+            for child in raw.node_direct_children(cursor) {
+                let node = raw.get_node(child).unwrap();
+                let mut child_states = vec![];
+                for &position in &states {
+                    match pattern.get(position) {
+                        Some(Segment::Exact(o)) if node == o => add_state(pattern, &mut child_states, position + 1),
+                        Some(Segment::Any) => add_state(pattern, &mut child_states, position + 1),
+                        Some(Segment::AnyDepth) => add_state(pattern, &mut child_states, position),
+                        _ => ()
+                    }
+                }
+                if !child_states.is_empty() {
+                    self.stack.push((child, child_states));
+                }
+            }
+            // Children are popped last first, so reverse them to visit them in order.
+            self.stack[first_child..].reverse();
+
+            if states.contains(&pattern.len()) {
+                if let Some(leaf) = raw.get_leaf(cursor) {
+                    return Some((self.path.clone(), leaf));
+                }
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,224 @@
+//! `RadixTrie`, a path-compressed trie where each node holds a run of key elements.
+
+use super::FlatTrie;
+use crate::raw::{RawTrie, RawCursor};
+
+use core::{iter, mem};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+/// A trie keyed by sequences, where each node holds a run of the key instead of a single element.
+///
+/// Every node that isn't the root either has a leaf or branches into at least two children, and
+/// no two siblings start with the same element. Inserting a key that diverges partway through a
+/// node splits that node, and removing keys merges nodes that are left with a single child and no
+/// leaf back into it. A trie of `n` keys has at most `2n` nodes, however long the keys are.
+///
+/// String keys can be stored as bytes with `str::as_bytes`. Nodes may then split a multi-byte
+/// character between them.
+///
+/// `as_flat` gives the underlying `FlatTrie`, for walking the trie with a `Cursor`.
+#[derive(Debug, Clone)]
+pub struct RadixTrie<N: Eq, L>(FlatTrie<Box<[N]>, L>);
+
+impl<N: Eq, L> Default for RadixTrie<N, L> {
+    fn default() -> RadixTrie<N, L> {
+        RadixTrie(FlatTrie::new())
+    }
+}
+
+impl<N: Eq + Clone, L> RadixTrie<N, L> {
+    pub fn new() -> RadixTrie<N, L> {
+        RadixTrie(FlatTrie::new())
+    }
+
+    /// The underlying trie, where each node holds a run of key elements.
+    pub fn as_flat(&self) -> &FlatTrie<Box<[N]>, L> {
+        &self.0
+    }
+
+    /// Gives `key` the leaf `leaf`, splitting the node where `key` diverges from the existing keys
+    /// if there is one. Returns the key's old leaf.
+    ///
+    /// # Panics
+    /// Panics if `key` is empty, since the root can't have a leaf.
+    pub fn insert(&mut self, key: &[N], leaf: L) -> Option<L> {
+        if key.is_empty() {
+            panic!("Attempted to give the root a leaf");
+        }
+        if let Some(old) = self.get_mut(key) {
+            return Some(mem::replace(old, leaf));
+        }
+
+        let raw = &mut self.0.0;
+        let mut cursor = RawCursor::root();
+        let mut rest = key;
+        loop {
+            let child = match find_raw_child(raw, cursor, &rest[0]) {
+                Some(child) => child,
+                None => {
+                    raw.insert_nodes_after(cursor, iter::once(rest.into()), Some(leaf));
+                    return None;
+                }
+            };
+
+            let node = raw.get_node(child).unwrap();
+            let common = common_prefix_len(node, rest);
+            if common < node.len() {
+                let (head, tail) = (node[..common].into(), node[common..].into());
+                raw.insert_node_below(child, tail);
+                *raw.get_node_mut(child).unwrap() = head;
+            }
+            cursor = child;
+            rest = &rest[common..];
+            if rest.is_empty() {
+                return raw.set_leaf(cursor, leaf);
+            }
+        }
+    }
+
+    pub fn get(&self, key: &[N]) -> Option<&L> {
+        let raw = &self.0.0;
+        self.find(key).and_then(|cursor| raw.get_leaf(cursor))
+    }
+
+    pub fn get_mut(&mut self, key: &[N]) -> Option<&mut L> {
+        let cursor = self.find(key)?;
+        self.0.0.get_leaf_mut(cursor)
+    }
+
+    /// Removes and returns `key`'s leaf. If that leaves its node without a leaf and with at most
+    /// one child, the node is removed or merged into its child, and its parent is merged with its
+    /// remaining child if it can be.
+    pub fn remove(&mut self, key: &[N]) -> Option<L> {
+        let cursor = self.find(key)?;
+        let raw = &mut self.0.0;
+        // The root has no parent, and no leaf to remove.
+        let parent = raw.node_parent(cursor)?;
+        let has_children = raw.node_direct_children(cursor).next().is_some();
+        // Without children, the node goes along with its leaf.
+        let leaf = raw.remove_leaf(cursor)?;
+        match has_children {
+            true => merge_into_only_child(raw, cursor),
+            false => merge_into_only_child(raw, parent)
+        }
+        Some(leaf)
+    }
+
+    /// Removes every key that starts with `prefix`, and returns how many there were.
+    pub fn prune_prefix(&mut self, prefix: &[N]) -> usize {
+        if prefix.is_empty() {
+            let removed = self.len();
+            self.0.clear();
+            return removed;
+        }
+        let cursor = match self.find_covering(prefix) {
+            Some(cursor) => cursor,
+            None => return 0
+        };
+        let removed = self.0.0.subtree_leaf_count(cursor);
+
+        let raw = &mut self.0.0;
+        let parent = raw.node_parent(cursor).unwrap();
+        raw.prune_node(cursor);
+        merge_into_only_child(raw, parent);
+        removed
+    }
+
+    /// Whether any key starts with `prefix`.
+    pub fn contains_prefix(&self, prefix: &[N]) -> bool {
+        match prefix.is_empty() {
+            true => !self.is_empty(),
+            false => self.find_covering(prefix).is_some()
+        }
+    }
+
+    /// Every key and its leaf, in pre-order.
+    pub fn iter(&self) -> impl '_ + Iterator<Item=(Vec<N>, &L)> {
+        let raw = &self.0.0;
+        // `lengths[d]` is the length of the key up to and including the node at depth `d`.
+        let mut key = Vec::new();
+        let mut lengths: Vec<usize> = Vec::new();
+        raw.descendants(RawCursor::root()).filter_map(move |cursor| {
+            let depth = cursor.depth() as usize;
+            lengths.truncate(depth);
+            key.truncate(lengths.last().cloned().unwrap_or(0));
+            key.extend_from_slice(raw.get_node(cursor).unwrap());
+            lengths.push(key.len());
+            raw.get_leaf(cursor).map(|leaf| (key.clone(), leaf))
+        })
+    }
+
+    /// The number of keys in the trie.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The number of nodes in the trie, not counting the root.
+    pub fn node_count(&self) -> usize {
+        self.0.node_count()
+    }
+
+    /// Finds the node that ends exactly at the end of `key`.
+    fn find(&self, key: &[N]) -> Option<RawCursor> {
+        let raw = &self.0.0;
+        let mut cursor = RawCursor::root();
+        let mut rest = key;
+        while !rest.is_empty() {
+            cursor = find_raw_child(raw, cursor, &rest[0])?;
+            let node = raw.get_node(cursor).unwrap();
+            match rest.starts_with(node) {
+                true => rest = &rest[node.len()..],
+                false => return None
+            }
+        }
+        Some(cursor)
+    }
+
+    /// Finds the highest node whose keys all start with `prefix`, which has to be non-empty.
+    fn find_covering(&self, prefix: &[N]) -> Option<RawCursor> {
+        let raw = &self.0.0;
+        let mut cursor = RawCursor::root();
+        let mut rest = prefix;
+        while !rest.is_empty() {
+            cursor = find_raw_child(raw, cursor, &rest[0])?;
+            let node = raw.get_node(cursor).unwrap();
+            let common = common_prefix_len(node, rest);
+            if common < rest.len() && common < node.len() {
+                return None;
+            }
+            rest = &rest[common..];
+        }
+        Some(cursor)
+    }
+}
+
+fn common_prefix_len<N: Eq>(a: &[N], b: &[N]) -> usize {
+    a.iter().zip(b).take_while(|&(a, b)| a == b).count()
+}
+
+fn find_raw_child<N: Eq, L>(raw: &RawTrie<Box<[N]>, L>, cursor: RawCursor, first: &N) -> Option<RawCursor> {
+    raw.node_direct_children(cursor).find(|&child| raw.get_node(child).unwrap()[0] == *first)
+}
+
+/// Merges the node at `cursor` with its child if it has exactly one child and no leaf.
+fn merge_into_only_child<N: Eq + Clone, L>(raw: &mut RawTrie<Box<[N]>, L>, cursor: RawCursor) {
+    let mergeable =
+        cursor != RawCursor::root() &&
+        raw.get_leaf(cursor).is_none() &&
+        raw.node_direct_children(cursor).nth(1).is_none() &&
+        raw.node_direct_children(cursor).next().is_some();
+    if !mergeable {
+        return;
+    }
+    let tail = raw.remove_node_below(cursor);
+    let node = raw.get_node_mut(cursor).unwrap();
+    let mut merged = mem::take(node).into_vec();
+    merged.extend(tail.into_vec());
+    *node = merged.into_boxed_slice();
+}
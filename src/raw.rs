@@ -1,15 +1,24 @@
-use std::cmp::Eq;
-use std::borrow::Borrow;
-use std::ops::Range;
-use std::iter::ExactSizeIterator;
-use odds::vec::VecExt;
+use core::cmp::Eq;
+use core::borrow::Borrow;
+use core::ops::Range;
+use core::iter::ExactSizeIterator;
+use core::{fmt, mem};
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::snapshot::SharedVec;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RawTrie<N: Eq, L> {
-    nodes: Vec<N>,
+    nodes: SharedVec<N>,
     /// The jumptions in the tree
-    jumps: Vec<Jump>,
-    leaves: Vec<L>
+    jumps: SharedVec<Jump>,
+    leaves: SharedVec<L>
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -20,6 +29,7 @@ pub struct RawCursor {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 enum MajorNode {
     LeafJump {
         leaf_index: usize,
@@ -34,6 +44,7 @@ enum MajorNode {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 struct Jump {
     parent_jump_index: isize,
     jump_to_node: isize,
@@ -44,19 +55,255 @@ struct Jump {
     depth: isize
 }
 
+/// An invariant of the trie's layout that doesn't hold. Variants that carry an index refer to the
+/// jump the problem was found at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegrityError {
+    /// The first jump isn't a root jump, or gives the root a leaf.
+    MissingRoot,
+    /// The jump isn't sorted after the jump before it.
+    UnsortedJump(usize),
+    /// The jump's parent isn't an earlier jump whose children include this one.
+    BadParent(usize),
+    /// The jump's child jump index doesn't point to its first child.
+    BadChild(usize),
+    /// The jump's depth doesn't follow on from its parent's.
+    BadDepth(usize),
+    /// The jump's nodes are out of bounds, or don't follow on from its parent or previous sibling.
+    BadNodeRange(usize),
+    /// The jump's leaf index isn't the next unused leaf.
+    BadLeafIndex(usize),
+    /// The jump starts with the same node as one of its earlier siblings.
+    DuplicateSibling(usize),
+    /// Some leaves aren't referenced by any jump.
+    UnusedLeaves,
+    /// Some nodes aren't covered by any jump.
+    UnusedNodes
+}
+
+/// The heap memory held by a trie's node, jump and leaf buffers, in bytes. This doesn't include
+/// memory that the nodes and leaves themselves own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryUsage {
+    pub nodes: usize,
+    pub jumps: usize,
+    pub leaves: usize,
+    /// How much of the total holds elements, rather than spare capacity.
+    pub used: usize
+}
+
+/// Builds a `RawTrie` from a depth-first, pre-order listing of its nodes.
+#[derive(Debug, Clone)]
+#[cfg_attr(not(any(feature = "serde", feature = "fuzzing")), allow(dead_code))]
+pub struct PreorderBuilder<N: Eq, L> {
+    nodes: Vec<N>,
+    shapes: Vec<NodeShape>,
+    leaf_pool: Vec<Option<L>>
+}
+
+/// A `Jump` flattened into plain fields, as stored in the binary format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JumpRecord {
+    pub parent_jump_index: isize,
+    pub jump_to_node: isize,
+    pub next_major_node_dist: usize,
+    pub depth: isize,
+    pub leaf_index: Option<usize>,
+    pub child_jump_index: Option<usize>
+}
+
+/// The position of a node in the pre-order listing, and the index of its leaf in the leaf pool.
+#[derive(Debug, Clone, Copy)]
+struct NodeShape {
+    depth: isize,
+    leaf: Option<usize>
+}
+
+/// A trie unpacked into a tree of linked nodes, so that many edits can be made before the jumps
+/// are rebuilt. See `RawTrie::edit`.
+#[derive(Debug)]
+pub struct EditTree<N, L> {
+    /// `nodes[0]` is the root. Pruned nodes stay in the list, but nothing links to them.
+    nodes: Vec<EditNode<N>>,
+    leaf_pool: Vec<Option<L>>
+}
+
+/// Cursors at every node below a starting node, in pre-order. See `RawTrie::descendants`.
+#[derive(Debug, Clone)]
+pub struct Descendants<'a, N: Eq, L> {
+    trie: &'a RawTrie<N, L>,
+    /// The next node in the current jump's chain.
+    chain: Option<RawCursor>,
+    /// Jumps still to visit, with the next one last.
+    jumps: Vec<usize>,
+    /// How many jumps the last node visited queued up.
+    queued: usize
+}
+
+#[derive(Debug)]
+struct EditNode<N> {
+    /// `None` for the root, and for nodes that have been moved back into the trie.
+    node: Option<N>,
+    leaf: Option<usize>,
+    children: Vec<usize>
+}
+
+impl<N: Eq, L> Default for RawTrie<N, L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<N: Eq, L> RawTrie<N, L> {
     pub fn new() -> RawTrie<N, L> {
         RawTrie {
-            nodes: vec![],
-            jumps: vec![Jump::default_root()],
-            leaves: vec![]
+            nodes: vec![].into(),
+            jumps: vec![Jump::default_root()].into(),
+            leaves: vec![].into()
+        }
+    }
+
+    pub fn with_capacity(nodes: usize, jumps: usize, leaves: usize) -> RawTrie<N, L> {
+        let mut jump_vec = Vec::with_capacity(jumps.max(1));
+        jump_vec.push(Jump::default_root());
+        RawTrie {
+            nodes: Vec::with_capacity(nodes).into(),
+            jumps: jump_vec.into(),
+            leaves: Vec::with_capacity(leaves).into()
+        }
+    }
+
+    pub fn reserve(&mut self, nodes: usize, jumps: usize, leaves: usize) {
+        self.nodes.reserve(nodes);
+        self.jumps.reserve(jumps);
+        self.leaves.reserve(leaves);
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+        self.jumps.shrink_to_fit();
+        self.leaves.shrink_to_fit();
+    }
+
+    /// Removes every node and leaf, keeping the allocated capacity.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.jumps.clear();
+        self.jumps.push(Jump::default_root());
+        self.leaves.clear();
+    }
+
+    pub fn memory_usage(&self) -> MemoryUsage {
+        use core::mem::size_of;
+
+        MemoryUsage {
+            nodes: self.nodes.capacity() * size_of::<N>(),
+            jumps: self.jumps.capacity() * size_of::<Jump>(),
+            leaves: self.leaves.capacity() * size_of::<L>(),
+            used:
+                self.nodes.len() * size_of::<N>() +
+                self.jumps.len() * size_of::<Jump>() +
+                self.leaves.len() * size_of::<L>()
+        }
+    }
+
+    /// Builds a trie directly from its parts, without checking that they describe a valid tree.
+    pub fn from_raw_parts<I>(nodes: Vec<N>, jumps: I, leaves: Vec<L>) -> RawTrie<N, L>
+        where I: IntoIterator<Item=JumpRecord>
+    {
+        RawTrie {
+            nodes: nodes.into(),
+            jumps: jumps.into_iter().map(Jump::from_record).collect::<Vec<_>>().into(),
+            leaves: leaves.into()
+        }
+    }
+
+    pub fn nodes(&self) -> &[N] {
+        &self.nodes
+    }
+
+    pub fn leaves(&self) -> &[L] {
+        &self.leaves
+    }
+
+    #[cfg_attr(not(feature = "rayon"), allow(dead_code))]
+    pub fn leaves_mut(&mut self) -> &mut [L] {
+        &mut self.leaves
+    }
+
+    pub fn jump_records<'a>(&'a self) -> impl 'a + ExactSizeIterator<Item=JumpRecord> {
+        self.jumps.iter().map(|j| j.to_record())
+    }
+
+    #[cfg_attr(not(feature = "rayon"), allow(dead_code))]
+    pub fn jump_count(&self) -> usize {
+        self.jumps.len()
+    }
+
+    /// The cursor and leaf of the jump's major node, if it has a leaf. Leaves are stored in the
+    /// order of the jumps they belong to.
+    #[cfg_attr(not(feature = "rayon"), allow(dead_code))]
+    pub fn jump_leaf(&self, jump_index: usize) -> Option<(RawCursor, &L)> {
+        let jump = self.jumps[jump_index];
+        jump.next_major_node.leaf_index().map(|leaf_index| {
+            let cursor = RawCursor {
+                node_index: jump.jump_to_node + jump.next_major_node_dist as isize,
+                parent_jump_index: jump_index,
+                depth: jump.depth + jump.next_major_node_dist as isize
+            };
+            (cursor, &self.leaves[leaf_index])
+        })
+    }
+
+    /// A trie with the same shape, made from `nodes` and `leaves` in place of this trie's.
+    ///
+    /// # Panics
+    /// Panics if there aren't as many nodes and leaves as this trie has.
+    #[cfg_attr(not(feature = "rayon"), allow(dead_code))]
+    pub fn with_parts<N2: Eq, L2>(&self, nodes: Vec<N2>, leaves: Vec<L2>) -> RawTrie<N2, L2> {
+        assert_eq!(nodes.len(), self.nodes.len(), "node count changed");
+        assert_eq!(leaves.len(), self.leaves.len(), "leaf count changed");
+        RawTrie {
+            nodes: nodes.into(),
+            jumps: self.jumps.clone(),
+            leaves: leaves.into()
+        }
+    }
+
+    /// A trie sharing this trie's buffers. Whichever of the two changes a buffer afterwards copies
+    /// it first.
+    pub fn share(&mut self) -> RawTrie<N, L>
+        where N: Clone, L: Clone
+    {
+        RawTrie {
+            nodes: self.nodes.share(),
+            jumps: self.jumps.share(),
+            leaves: self.leaves.share()
         }
     }
 
+    /// Another trie sharing the buffers of a trie made by `share`.
+    pub fn reshare(&self) -> RawTrie<N, L> {
+        RawTrie {
+            nodes: self.nodes.reshare(),
+            jumps: self.jumps.reshare(),
+            leaves: self.leaves.reshare()
+        }
+    }
+
+    pub fn shares_buffers(&self, other: &RawTrie<N, L>) -> bool {
+        self.nodes.ptr_eq(&other.nodes) && self.jumps.ptr_eq(&other.jumps) && self.leaves.ptr_eq(&other.leaves)
+    }
+
     pub fn get_node(&self, cursor: RawCursor) -> Option<&N> {
         self.nodes.get(cursor.node_index as usize)
     }
 
+    /// Changing the node mustn't make it equal to one of its siblings.
+    pub fn get_node_mut(&mut self, cursor: RawCursor) -> Option<&mut N> {
+        self.nodes.get_mut(cursor.node_index as usize)
+    }
+
     pub fn node_parent(&self, cursor: RawCursor) -> Option<RawCursor> {
         let parent_jump = self.jumps[cursor.parent_jump_index];
         match cursor.node_index == parent_jump.jump_to_node {
@@ -77,7 +324,7 @@ impl<N: Eq, L> RawTrie<N, L> {
     }
 
     pub fn node_direct_children<'a>(&'a self, cursor: RawCursor) -> impl 'a + Iterator<Item=RawCursor> {
-        use std::ops::RangeFrom;
+        use core::ops::RangeFrom;
         let parent_jump = self.jumps[cursor.parent_jump_index];
 
         let direct_child: Option<RawCursor>;
@@ -88,7 +335,7 @@ impl<N: Eq, L> RawTrie<N, L> {
                     node_index: cursor.node_index + 1,
                     parent_jump_index: cursor.parent_jump_index,
                     depth: cursor.depth + 1
-                }).into_iter().filter(|_| parent_jump.cursor_has_children(cursor)).next();
+                }).into_iter().find(|_| parent_jump.cursor_has_children(cursor));
                 child_jump_search = self.jumps.len()..;
             }
             (MajorNode::Leaf{..}, true) => {
@@ -139,12 +386,9 @@ impl<N: Eq, L> RawTrie<N, L> {
         loop {
             match jump.next_major_node {
                 MajorNode::Leaf{..} => break,
-                MajorNode::LeafJump{child_jump_index, ..} |
-                MajorNode::Jump{child_jump_index} => {
-                    jump_index = self.jumps[child_jump_index + 1..].windows(2).zip(child_jump_index + 1..)
-                        .find(|&(x, _)| x[0].parent_jump_index != x[1].parent_jump_index)
-                        .map(|(_, i)| i).unwrap_or(self.jumps.len() - 1);
-
+                MajorNode::LeafJump{..} |
+                MajorNode::Jump{..} => {
+                    jump_index = self.child_jump_range(jump_index).end - 1;
                     jump = self.jumps[jump_index];
                 }
             }
@@ -157,6 +401,43 @@ impl<N: Eq, L> RawTrie<N, L> {
         }
     }
 
+    /// The range of indices in `nodes` taken up by the node at `cursor` and all of its
+    /// descendants.
+    pub fn subtree_node_range(&self, cursor: RawCursor) -> Range<usize> {
+        let start = match cursor == RawCursor::root() {
+            true => 0,
+            false => cursor.node_index as usize
+        };
+        start..(self.last_child_node(cursor).node_index + 1) as usize
+    }
+
+    pub fn subtree_leaf_count(&self, cursor: RawCursor) -> usize {
+        // The cursor's jump has its major node in the subtree, and the jumps below it take up one
+        // run of `jumps` per level.
+        let mut jumps = cursor.parent_jump_index..cursor.parent_jump_index + 1;
+        let mut leaf_count = 0;
+        while !jumps.is_empty() {
+            leaf_count += count_leaves(&self.jumps[jumps.clone()]);
+            jumps = self.next_level(jumps);
+        }
+        leaf_count
+    }
+
+    pub fn leaf_count(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// The number of nodes along the longest path from the root.
+    pub fn height(&self) -> usize {
+        self.jumps.iter()
+            .map(|j| (j.depth + j.next_major_node_dist as isize + 1) as usize)
+            .max().unwrap_or(0)
+    }
+
     fn get_leaf_index(&self, cursor: RawCursor) -> Option<usize> {
         let parent_jump = self.jumps[cursor.parent_jump_index];
         match parent_jump.next_major_node_dist == (cursor.node_index - parent_jump.jump_to_node) as usize {
@@ -189,21 +470,21 @@ impl<N: Eq, L> RawTrie<N, L> {
             let parent_jump = self.jumps[cursor.parent_jump_index];
 
             if parent_jump.cursor_at_next_major_node(cursor) {
-                let cursor_opt: Option<RawCursor>;
-                match parent_jump.next_major_node {
+                let cursor_opt = match parent_jump.next_major_node {
                     MajorNode::Leaf{..} => return Err((cursor, i)),
                     MajorNode::LeafJump{..} |
-                    MajorNode::Jump{..} => cursor_opt =
+                    MajorNode::Jump{..} =>
                         self.node_direct_children(cursor)
                             .map(|cursor| (&self.nodes[cursor.node_index as usize], cursor))
                             .find(|&(node, _)| child == node.borrow()).map(|(_, cursor)| cursor)
-                }
+                };
                 match cursor_opt {
                     Some(nc) => cursor = nc,
                     None => return Err((cursor, i))
                 }
             } else if child == self.nodes[(cursor.node_index + 1) as usize].borrow() {
                 cursor.node_index += 1;
+                cursor.depth += 1;
             } else {
                 return Err((cursor, i))
             }
@@ -212,6 +493,9 @@ impl<N: Eq, L> RawTrie<N, L> {
         Ok(cursor)
     }
 
+    // `common_ancestor` and `route_to_descendant` are for `Cursor::enter_route`, which is still
+    // commented out.
+    #[allow(dead_code)]
     pub fn common_ancestor(&self, left: RawCursor, right: RawCursor) -> RawCursor {
         let mut left_parent_index = left.parent_jump_index as isize;
         let mut right_parent_index = right.parent_jump_index as isize;
@@ -235,13 +519,14 @@ impl<N: Eq, L> RawTrie<N, L> {
         }
     }
 
+    #[allow(dead_code)]
     pub fn route_to_descendant<'a>(&'a self, node: RawCursor, descendant: RawCursor) -> impl 'a + Iterator<Item=&'a N> {
-        use std::{cmp, iter};
+        use core::{cmp, iter};
 
         let mut parent_jump_index = node.parent_jump_index;
         let mut parent_jump = self.jumps[parent_jump_index];
 
-        let node_range_end = move |jump: Jump| (1 + cmp::min(descendant.node_index, (jump.jump_to_node + jump.next_major_node_dist as isize))) as usize;
+        let node_range_end = move |jump: Jump| (1 + cmp::min(descendant.node_index, jump.jump_to_node + jump.next_major_node_dist as isize)) as usize;
 
         self.nodes[(node.node_index + 1) as usize..node_range_end(parent_jump)].iter().chain(
             // This iterator is what happens when an iterator equivalent of a `while` loop doesn't exist.
@@ -271,7 +556,7 @@ impl<N: Eq, L> RawTrie<N, L> {
                         None
                     }
                 }
-            }).flat_map(|nodes| nodes).fuse()
+            }).flatten().fuse()
         )
     }
 
@@ -281,14 +566,11 @@ impl<N: Eq, L> RawTrie<N, L> {
     {
         let mut leaf_jump_index_opt = None;
         for (jump, jump_index) in ranges.into_iter().flat_map(|r| self.jumps[r.clone()].iter().zip(r)) {
-            match jump.next_major_node {
-                MajorNode::Leaf{leaf_index} if leaf_index != -1 => {
-                    if by(&self.leaves[leaf_index as usize]) {
-                        leaf_jump_index_opt = Some(jump_index);
-                        break;
-                    }
-                },
-                _ => ()
+            if let Some(leaf_index) = jump.next_major_node.leaf_index() {
+                if by(&self.leaves[leaf_index]) {
+                    leaf_jump_index_opt = Some(jump_index);
+                    break;
+                }
             }
         }
 
@@ -305,13 +587,89 @@ impl<N: Eq, L> RawTrie<N, L> {
     pub fn find_leaf_after_wrapping_by<F>(&self, cursor: RawCursor, by: F) -> Option<RawCursor>
         where F: FnMut(&L) -> bool
     {
-        if self.leaves.len() == 0 {
+        if self.leaves.is_empty() {
             return None;
         }
 
         self.find_leaf_by(by, [cursor.parent_jump_index + 1..self.jumps.len(), 0..cursor.parent_jump_index + 1].iter().cloned())
     }
 
+    /// Returns a cursor at the node stored at `node_index` in the `nodes` vector. A jump's
+    /// children are sorted by the node they start at, so this walks down from the root, binary
+    /// searching each jump's children for the one whose subtree holds the node.
+    ///
+    /// # Panics
+    /// Panics if `node_index` is out of bounds.
+    pub fn node_index_cursor(&self, node_index: usize) -> RawCursor {
+        if self.nodes.len() <= node_index {
+            panic!("node index out of bounds");
+        }
+
+        let node_index = node_index as isize;
+        let mut jump_index = 0;
+        loop {
+            let jump = self.jumps[jump_index];
+            if node_index <= jump.jump_to_node + jump.next_major_node_dist as isize {
+                return RawCursor {
+                    node_index: node_index,
+                    parent_jump_index: jump_index,
+                    depth: jump.depth + (node_index - jump.jump_to_node)
+                };
+            }
+
+            let first_child_jump_index = jump.next_major_node.child_jump_index().expect("tree corrupted");
+            let children = &self.jumps[first_child_jump_index..];
+            let children = &children[..children.partition_point(|j| j.parent_jump_index == jump_index as isize)];
+            jump_index = first_child_jump_index + children.partition_point(|j| j.jump_to_node <= node_index) - 1;
+        }
+    }
+
+    /// Looks up `cursor`'s node again, for cursors taken before the jumps were rebuilt. Only valid
+    /// if the node's index hasn't changed since.
+    pub fn refresh_cursor(&self, cursor: RawCursor) -> RawCursor {
+        match cursor == RawCursor::root() {
+            true => RawCursor::root(),
+            false => self.node_index_cursor(cursor.node_index as usize)
+        }
+    }
+
+    /// Looks up `cursor`'s node again after the nodes in `pruned_range` have been pruned. Cursors
+    /// inside the pruned range are moved to `pruned_parent`.
+    pub fn cursor_after_prune(&self, cursor: RawCursor, pruned_range: Range<usize>, pruned_parent: RawCursor) -> RawCursor {
+        match cursor.node_index {
+            -1 => RawCursor::root(),
+            i if (i as usize) < pruned_range.start => self.refresh_cursor(cursor),
+            i if (i as usize) < pruned_range.end => self.refresh_cursor(pruned_parent),
+            i => self.node_index_cursor(i as usize - pruned_range.len())
+        }
+    }
+
+    pub fn find_node_after_wrapping_by<F>(&self, cursor: RawCursor, mut by: F) -> Option<RawCursor>
+        where F: FnMut(&N) -> bool
+    {
+        let search_start = (cursor.node_index + 1) as usize;
+        let node_index_opt = self.nodes[search_start..].iter().position(&mut by).map(|i| i + search_start)
+            .or_else(|| self.nodes[..search_start].iter().position(by));
+
+        node_index_opt.map(|i| self.node_index_cursor(i))
+    }
+
+    /// Scans `nodes` in order, and only looks up the jumps of the nodes that match.
+    pub fn find_nodes_by<'a, F>(&'a self, mut by: F) -> impl 'a + Iterator<Item=RawCursor>
+        where F: 'a + FnMut(&N) -> bool
+    {
+        self.nodes.iter().enumerate()
+            .filter(move |&(_, node)| by(node))
+            .map(move |(node_index, _)| self.node_index_cursor(node_index))
+    }
+
+    /// Inserts `nodes` as a chain below the node at `cursor`, after any existing children, and
+    /// returns a cursor at the last inserted node. `leaf_opt` becomes that node's leaf.
+    ///
+    /// Cursors at `cursor`'s node and its ancestors stay valid.
+    ///
+    /// # Panics
+    /// Panics if the first node is already a child of the node at `cursor`.
     pub fn insert_nodes_after<I>(&mut self, cursor: RawCursor, nodes: I, leaf_opt: Option<L>) -> RawCursor
         where I: IntoIterator<Item=N>,
               I::IntoIter: ExactSizeIterator
@@ -321,421 +679,1056 @@ impl<N: Eq, L> RawTrie<N, L> {
             return cursor;
         }
 
-        let mut num_children = 0;
         let num_nodes_insert = nodes.len();
         let first_node = nodes.next().unwrap();
         for child_cursor in self.node_direct_children(cursor) {
-            num_children += 1;
             if self.nodes[child_cursor.node_index as usize] == first_node {
                 panic!("Attempt to insert node when node already exists");
             }
         }
 
+        // Partway along a chain, the rest of the chain has to get a jump of its own before the new
+        // nodes can go next to it.
+        let jump_index = cursor.parent_jump_index;
+        if !self.jumps[jump_index].cursor_at_next_major_node(cursor) {
+            self.split_jump(cursor);
+        }
 
-        let insert_node_index: usize;
-        // Insert continue jump: Insert a jump to the node directly after the selected node.
-        // Insert split jump: Insert a jump to the node being inserted now
-        let (insert_continue_jump, insert_split_jump): (bool, bool);
-        let cursor_parent_jump = self.jumps[cursor.parent_jump_index];
-        let parent_has_leaf = match cursor_parent_jump.next_major_node {
-            MajorNode::Leaf{leaf_index: -1} |
-            MajorNode::Jump{..}            => false,
-            MajorNode::LeafJump{..}         |
-            MajorNode::Leaf{..}            => true
+        // Nodes are stored in pre-order, so the new nodes go right after the cursor's last
+        // descendant.
+        let insert_node_index = self.subtree_node_range(cursor).end;
+        self.offset_nodes(insert_node_index, num_nodes_insert as isize);
+        self.nodes.splice(insert_node_index..insert_node_index, Some(first_node).into_iter().chain(nodes));
+
+        let inserted_jump_index = match self.jumps[jump_index].next_major_node {
+            // Without a leaf or children, the chain can carry on through the new nodes.
+            MajorNode::Leaf{leaf_index: -1} => {
+                self.jumps[jump_index].next_major_node_dist += num_nodes_insert;
+                jump_index
+            },
+            _ => self.push_child_jump(jump_index, Jump {
+                parent_jump_index: jump_index as isize,
+                jump_to_node: insert_node_index as isize,
+                next_major_node_dist: num_nodes_insert - 1,
+                next_major_node: MajorNode::Leaf{ leaf_index: -1 },
+                depth: cursor.depth + 1
+            })
         };
-        let cursor_at_next_major_node = cursor_parent_jump.cursor_at_next_major_node(cursor);
+        if let Some(leaf) = leaf_opt {
+            self.insert_leaf(inserted_jump_index, leaf);
+        }
+        self.verify_tree_integrity();
+
+        RawCursor {
+            node_index: (insert_node_index + num_nodes_insert - 1) as isize,
+            parent_jump_index: inserted_jump_index,
+            depth: cursor.depth + num_nodes_insert as isize
+        }
+    }
+
+    /// Removes the node at `cursor`, along with all of its descendants and their leaves. Pruning
+    /// the root clears the trie.
+    ///
+    /// Cursors at the pruned node's ancestors stay valid.
+    pub fn prune_node(&mut self, cursor: RawCursor) {
+        if cursor == RawCursor::root() {
+            self.clear();
+        } else {
+            let node_range = self.subtree_node_range(cursor);
+            let jump_index = cursor.parent_jump_index;
+            let jump = self.jumps[jump_index];
+            let parent_jump_index = jump.parent_jump_index as usize;
+            let child_jumps = self.child_jump_range(jump_index);
+
+            self.unlink_jumps();
+            let prunes_jump = cursor.node_index == jump.jump_to_node;
+            let merge_parent = match prunes_jump {
+                true => {
+                    self.remove_jump_subtrees(jump_index..jump_index + 1);
+                    let parent_jump = &mut self.jumps[parent_jump_index];
+                    parent_jump.next_major_node = match parent_jump.next_major_node {
+                        MajorNode::LeafJump{leaf_index, child_jump_index: 1} => MajorNode::Leaf{ leaf_index: leaf_index as isize },
+                        MajorNode::LeafJump{leaf_index, child_jump_index} => MajorNode::LeafJump {
+                            leaf_index: leaf_index,
+                            child_jump_index: child_jump_index - 1
+                        },
+                        MajorNode::Jump{child_jump_index: 1} => MajorNode::Leaf{ leaf_index: -1 },
+                        MajorNode::Jump{child_jump_index} => MajorNode::Jump{ child_jump_index: child_jump_index - 1 },
+                        MajorNode::Leaf{..} => panic!("tree corrupted")
+                    };
+                    // A parent left with one child and no leaf should carry on through the child.
+                    parent_jump.next_major_node == MajorNode::Jump{ child_jump_index: 1 }
+                },
+                false => {
+                    // The chain now ends right above the cursor, without a leaf or children.
+                    self.remove_jump_subtrees(child_jumps);
+                    if let Some(leaf_index) = jump.next_major_node.leaf_index() {
+                        self.leaves.remove(leaf_index);
+                    }
+                    let jump_mut = &mut self.jumps[jump_index];
+                    jump_mut.next_major_node_dist = (cursor.node_index - 1 - jump.jump_to_node) as usize;
+                    jump_mut.next_major_node = MajorNode::Leaf{ leaf_index: -1 };
+                    false
+                }
+            };
+
+            self.nodes.drain(node_range.clone());
+            self.offset_nodes(node_range.end, -(node_range.len() as isize));
+            self.relink_jumps();
+            if merge_parent {
+                self.merge_only_child(parent_jump_index);
+            }
+            self.verify_tree_integrity();
+        }
+    }
 
-        match num_children {
-            0 => {
-                insert_node_index = (cursor.node_index + 1) as usize;
-                if let Some(jump) = self.jumps.get_mut(cursor.parent_jump_index) {
-                    jump.next_major_node_dist += num_nodes_insert;
+    /// Removes and returns the leaf at `cursor`, then prunes the highest node that's left without
+    /// any leaves or other children, walking up from `cursor`'s node.
+    pub fn remove_leaf(&mut self, cursor: RawCursor) -> Option<L> {
+        let leaf_index = self.get_leaf_index(cursor)?;
+        let jump_index = cursor.parent_jump_index;
+        let leaf = self.leaves.remove(leaf_index);
+        self.offset_leaf_indices(jump_index + 1, -1);
+
+        let jump = &mut self.jumps[jump_index];
+        match jump.next_major_node {
+            MajorNode::LeafJump{child_jump_index, ..} => {
+                jump.next_major_node = MajorNode::Jump{ child_jump_index: child_jump_index };
+                if self.child_jump_range(jump_index).len() == 1 {
+                    self.merge_only_child(jump_index);
                 }
-                insert_continue_jump = false;
-                insert_split_jump = parent_has_leaf;
-            },
-            1 => {
-                insert_node_index = (self.last_child_node(cursor).node_index + 1) as usize;
-                insert_continue_jump = !cursor_at_next_major_node;
-                insert_split_jump = true;
             },
             _ => {
-                insert_node_index = (self.last_child_node(cursor).node_index + 1) as usize;
-                insert_continue_jump = false;
-                insert_split_jump = true;
+                jump.next_major_node = MajorNode::Leaf{ leaf_index: -1 };
+                let mut emptied = cursor;
+                while let Some(parent) = self.node_parent(emptied) {
+                    let parent_needed =
+                        parent == RawCursor::root() ||
+                        self.get_leaf(parent).is_some() ||
+                        self.node_direct_children(parent).nth(1).is_some();
+                    if parent_needed {
+                        break;
+                    }
+                    emptied = parent;
+                }
+                self.prune_node(emptied);
             }
         }
+        self.verify_tree_integrity();
+        Some(leaf)
+    }
 
-        let (insert_continue_jump_index, insert_split_jump_index): (usize, usize);
-        let leaf_jump_index: usize;
+    /// Gives the node at `cursor` the leaf `leaf`, and returns its old leaf.
+    ///
+    /// Cursors at `cursor`'s node and its ancestors stay valid.
+    ///
+    /// # Panics
+    /// Panics if `cursor` is at the root.
+    pub fn set_leaf(&mut self, cursor: RawCursor, leaf: L) -> Option<L> {
+        if cursor == RawCursor::root() {
+            panic!("Attempted to give the root a leaf");
+        }
+        if let Some(old_leaf) = self.get_leaf_mut(cursor) {
+            return Some(mem::replace(old_leaf, leaf));
+        }
 
-        let jump_depth = cursor_parent_jump.depth + cursor.node_index - cursor_parent_jump.jump_to_node + 1;
-        match insert_continue_jump {
-            false => insert_continue_jump_index = usize::max_value(),
-            true => {
-                let jump = Jump {
-                    depth: jump_depth,
-                    parent_jump_index: cursor.parent_jump_index as isize,
-                    jump_to_node: cursor.node_index + 1,
-                    next_major_node_dist: (cursor_parent_jump.jump_to_node + cursor_parent_jump.next_major_node_dist as isize - (cursor.node_index + 1)) as usize,
-                    next_major_node: match cursor_parent_jump.next_major_node {
-                        MajorNode::Jump{child_jump_index} => MajorNode::Jump{child_jump_index: child_jump_index + 1},
-                        MajorNode::Leaf{..} => cursor_parent_jump.next_major_node,
-                        MajorNode::LeafJump{child_jump_index, leaf_index} => MajorNode::LeafJump {
-                            child_jump_index: child_jump_index + 1,
-                            leaf_index
-                        }
-                    }
-                };
+        if !self.jumps[cursor.parent_jump_index].cursor_at_next_major_node(cursor) {
+            self.split_jump(cursor);
+        }
+        self.insert_leaf(cursor.parent_jump_index, leaf);
+        self.verify_tree_integrity();
+        None
+    }
+
+    /// Inserts `node` as the only child of the node at `cursor`, which hands its leaf and children
+    /// over to it. Returns a cursor at the inserted node.
+    ///
+    /// Cursors at `cursor`'s node and its ancestors stay valid.
+    pub fn insert_node_below(&mut self, cursor: RawCursor, node: N) -> RawCursor {
+        // The cursor's node ends up partway along its jump's chain, and the inserted node takes
+        // its place in the chain if it was the major node.
+        let node_index = (cursor.node_index + 1) as usize;
+        self.offset_nodes(node_index, 1);
+        self.nodes.insert(node_index, node);
+        self.jumps[cursor.parent_jump_index].next_major_node_dist += 1;
+        self.offset_depths(cursor.parent_jump_index, 1);
+        self.verify_tree_integrity();
+
+        RawCursor {
+            node_index: node_index as isize,
+            parent_jump_index: cursor.parent_jump_index,
+            depth: cursor.depth + 1
+        }
+    }
+
+    /// Removes the only child of the node at `cursor`, which takes over the child's leaf and
+    /// children, and returns the removed node. Undoes `insert_node_below`.
+    ///
+    /// Cursors at `cursor`'s node and its ancestors stay valid.
+    ///
+    /// # Panics
+    /// Panics if the node at `cursor` has a leaf or doesn't have exactly one child.
+    pub fn remove_node_below(&mut self, cursor: RawCursor) -> N {
+        // Only nodes partway along a chain have one child and no leaf.
+        if self.jumps[cursor.parent_jump_index].cursor_at_next_major_node(cursor) {
+            panic!("Attempted to remove the child of a node with a leaf or without exactly one child");
+        }
+
+        let node_index = (cursor.node_index + 1) as usize;
+        let node = self.nodes.remove(node_index);
+        self.offset_nodes(node_index + 1, -1);
+        self.jumps[cursor.parent_jump_index].next_major_node_dist -= 1;
+        self.offset_depths(cursor.parent_jump_index, -1);
+        self.verify_tree_integrity();
+        node
+    }
 
-                insert_continue_jump_index = self.jumps[cursor.parent_jump_index..]
-                    .binary_search(&jump).unwrap_err() + cursor.parent_jump_index;
-                self.jumps.insert(insert_continue_jump_index, jump);
+    /// Removes every node without any leaves in its subtree.
+    pub fn compact(&mut self) {
+        let shapes = self.node_shapes();
+        let leaf_pool = self.leaves.drain(..).map(Some).collect();
+        self.rebuild_without_leafless(&shapes, leaf_pool);
+    }
+
+    /// Lists the shape of every node in `nodes`. Each shape's `leaf` is an index into `leaves`.
+    fn node_shapes(&self) -> Vec<NodeShape> {
+        let mut shapes = vec![NodeShape{ depth: 0, leaf: None }; self.nodes.len()];
+        for jump in self.jumps.iter() {
+            for dist in 0..jump.next_major_node_dist + 1 {
+                let node_index = jump.jump_to_node + dist as isize;
+                if node_index != -1 {
+                    shapes[node_index as usize].depth = jump.depth + dist as isize;
+                }
+            }
+
+            if let Some(leaf_index) = jump.next_major_node.leaf_index() {
+                let major_node = jump.jump_to_node + jump.next_major_node_dist as isize;
+                shapes[major_node as usize].leaf = Some(leaf_index);
             }
         }
-        match insert_split_jump {
-            false => {
-                insert_split_jump_index = usize::max_value();
-                leaf_jump_index = cursor.parent_jump_index;
-            },
-            true => {
-                let jump = Jump {
-                    depth: jump_depth,
-                    parent_jump_index: cursor.parent_jump_index as isize,
-                    jump_to_node: insert_node_index as isize,
-                    next_major_node_dist: num_nodes_insert - 1,
-                    next_major_node: MajorNode::Leaf{ leaf_index: -1 }
-                };
+        shapes
+    }
+
+    /// The indices of the children of the jump at `jump_index`, or where they'd go if it has
+    /// none.
+    fn child_jump_range(&self, jump_index: usize) -> Range<usize> {
+        let jump_index = jump_index as isize;
+        let start = self.jumps.partition_point(|j| j.parent_jump_index < jump_index);
+        let end = self.jumps.partition_point(|j| j.parent_jump_index <= jump_index);
+        start..end
+    }
+
+    /// The children of the jumps in `jumps`, which have to be next to each other on one level, or
+    /// where they'd go if there are none. Only looks at the jumps after `jumps`.
+    fn next_level(&self, jumps: Range<usize>) -> Range<usize> {
+        let below = &self.jumps[jumps.end..];
+        let start = below.partition_point(|j| j.parent_jump_index < jumps.start as isize);
+        let end = below.partition_point(|j| j.parent_jump_index < jumps.end as isize);
+        jumps.end + start..jumps.end + end
+    }
 
-                insert_split_jump_index =
-                    self.jumps[cursor.parent_jump_index..]
-                        .binary_search(&jump).unwrap_err() + cursor.parent_jump_index;
-                leaf_jump_index = insert_split_jump_index;
-                self.jumps.insert(insert_split_jump_index, jump);
+    /// The index in `leaves` of the first leaf of a jump at or after `jump_index`, which is where
+    /// a leaf given to the jump at `jump_index` goes.
+    fn leaf_position(&self, jump_index: usize) -> usize {
+        self.jumps[jump_index..].iter()
+            .find_map(|j| j.next_major_node.leaf_index())
+            .unwrap_or(self.leaves.len())
+    }
+
+    /// Adds `by` to the start of every jump that starts at or after the node at `node_index`.
+    fn offset_nodes(&mut self, node_index: usize, by: isize) {
+        for jump in self.jumps.iter_mut() {
+            if node_index as isize <= jump.jump_to_node {
+                jump.jump_to_node += by;
             }
         }
+    }
 
-        let mut leaf_insert_index: usize = 0;
-        let mut children_rotate_range: Option<Range<usize>> = None;
-        let mut children_rotate_to = self.jumps.len();
+    /// Adds `by` to the depth of every jump below the jump at `jump_index`.
+    fn offset_depths(&mut self, jump_index: usize, by: isize) {
+        let mut jumps = self.child_jump_range(jump_index);
+        while !jumps.is_empty() {
+            for jump in &mut self.jumps[jumps.clone()] {
+                jump.depth += by;
+            }
+            jumps = self.next_level(jumps);
+        }
+    }
 
-        for jump_index in 0..self.jumps.len() {
-            let incr_jump_indices: bool;
-            {
-                let jump = self.jumps[jump_index];
-
-                incr_jump_indices = !(
-                    jump.parent_jump_index == -1 ||
-                    jump_index == cursor.parent_jump_index ||
-                    jump_index == insert_continue_jump_index ||
-                    jump_index == insert_split_jump_index
-                );
-            }
-
-            if incr_jump_indices {
-                let jump = &mut self.jumps[jump_index];
-                match jump.next_major_node {
-                    MajorNode::LeafJump{ref mut child_jump_index, ..} |
-                    MajorNode::Jump{ref mut child_jump_index} => {
-                        if insert_continue_jump_index <= *child_jump_index {
-                            *child_jump_index += 1;
-                        }
-                        if insert_split_jump_index <= *child_jump_index {
-                            *child_jump_index += 1;
-                        }
-                    },
-                    MajorNode::Leaf{..} => ()
-                }
+    /// Adds `by` to the leaf index of every jump from `jump_index` on.
+    fn offset_leaf_indices(&mut self, jump_index: usize, by: isize) {
+        for jump in &mut self.jumps[jump_index..] {
+            match jump.next_major_node {
+                MajorNode::LeafJump{ref mut leaf_index, ..} => *leaf_index = (*leaf_index as isize + by) as usize,
+                MajorNode::Leaf{ref mut leaf_index} if *leaf_index != -1 => *leaf_index += by,
+                MajorNode::Leaf{..} |
+                MajorNode::Jump{..} => ()
+            }
+        }
+    }
 
-                if insert_node_index <= jump.jump_to_node as usize && jump.jump_to_node != -1 {
-                    jump.jump_to_node += num_nodes_insert as isize;
-                }
-                if insert_continue_jump_index <= jump.parent_jump_index as usize {
-                    jump.parent_jump_index += 1;
-                }
-                if insert_split_jump_index <= jump.parent_jump_index as usize {
-                    jump.parent_jump_index += 1;
-                }
+    /// Gives the major node of the jump at `jump_index`, which doesn't have a leaf, the leaf `leaf`.
+    fn insert_leaf(&mut self, jump_index: usize, leaf: L) {
+        let leaf_index = self.leaf_position(jump_index + 1);
+        self.leaves.insert(leaf_index, leaf);
+        self.offset_leaf_indices(jump_index + 1, 1);
+
+        let jump = &mut self.jumps[jump_index];
+        jump.next_major_node = match jump.next_major_node {
+            MajorNode::Leaf{leaf_index: -1} => MajorNode::Leaf{ leaf_index: leaf_index as isize },
+            MajorNode::Jump{child_jump_index} => MajorNode::LeafJump {
+                leaf_index: leaf_index,
+                child_jump_index: child_jump_index
+            },
+            MajorNode::Leaf{..} |
+            MajorNode::LeafJump{..} => panic!("Attempted to insert a leaf over another leaf")
+        };
+    }
 
-                // If a continue jump has been inserted, children of the original jump have to have
-                // their `parent_jump_index`s updated and be shifted around in the `jumps` vec to
-                // maintain sorted order. This block updates the `parent_jump_index` and defines the
-                // range of jumps that must be shifted.
-                if insert_continue_jump && cursor.parent_jump_index == jump.parent_jump_index as usize {
-                    jump.parent_jump_index = insert_continue_jump_index as isize;
-                    match children_rotate_range {
-                        Some(ref mut range) => range.end += 1,
-                        None => {
-                            children_rotate_range = Some(jump_index..jump_index + 1);
-                        }
-                    }
-                }
+    /// Adds `jump` as the last child of the jump at `jump_index`, and returns its index.
+    fn push_child_jump(&mut self, jump_index: usize, jump: Jump) -> usize {
+        let child_jump_index = self.child_jump_range(jump_index).end;
+        self.unlink_jumps();
+        let parent_jump = &mut self.jumps[jump_index];
+        parent_jump.next_major_node = match parent_jump.next_major_node {
+            MajorNode::Leaf{leaf_index: -1} => MajorNode::Jump{ child_jump_index: 1 },
+            MajorNode::Leaf{leaf_index} => MajorNode::LeafJump {
+                leaf_index: leaf_index as usize,
+                child_jump_index: 1
+            },
+            MajorNode::Jump{child_jump_index} => MajorNode::Jump{ child_jump_index: child_jump_index + 1 },
+            MajorNode::LeafJump{leaf_index, child_jump_index} => MajorNode::LeafJump {
+                leaf_index: leaf_index,
+                child_jump_index: child_jump_index + 1
             }
+        };
+        self.jumps.insert(child_jump_index, jump);
+        self.relink_jumps();
+        child_jump_index
+    }
 
-            if let Some(range) = children_rotate_range.clone() {
-                let jump = self.jumps[jump_index];
+    /// Ends the chain of `cursor`'s jump at `cursor`'s node, and moves the rest of the chain into
+    /// a new jump that's its only child. Every jump below moves down a level.
+    fn split_jump(&mut self, cursor: RawCursor) {
+        let jump_index = cursor.parent_jump_index;
+        let jump = self.jumps[jump_index];
+        let child_jumps = self.child_jump_range(jump_index);
+
+        // The new jump takes the leaf, which goes after the leaves of the jumps in between.
+        let mut leaf_start = self.leaf_position(child_jumps.start);
+        if let Some(leaf_index) = jump.next_major_node.leaf_index() {
+            self.leaves[leaf_index..leaf_start].rotate_left(1);
+        }
 
-                match self.jumps[range.end - 1] < jump {
-                    true => {
-                        if children_rotate_to == self.jumps.len() {
-                            children_rotate_to = jump_index;
-                        }
-                        if range.contains(jump.parent_jump_index as usize) {
-                            self.jumps[jump_index].parent_jump_index += ((children_rotate_to - range.start) - range.len()) as isize;
-                        }
-                    },
-                    false => ()
+        self.unlink_jumps();
+        let rest = Jump {
+            parent_jump_index: jump_index as isize,
+            jump_to_node: cursor.node_index + 1,
+            next_major_node_dist: (jump.jump_to_node + jump.next_major_node_dist as isize - cursor.node_index - 1) as usize,
+            next_major_node: self.jumps[jump_index].next_major_node,
+            depth: cursor.depth + 1
+        };
+        let jump_mut = &mut self.jumps[jump_index];
+        jump_mut.next_major_node_dist = (cursor.node_index - jump.jump_to_node) as usize;
+        jump_mut.next_major_node = MajorNode::Jump{ child_jump_index: 1 };
+
+        // Each level's jumps below the split move to where the next level's were, after the
+        // jumps in between.
+        let mut moved = child_jumps.clone();
+        while !moved.is_empty() {
+            let next = self.next_level(moved.clone());
+            let moved_leaves = count_leaves(&self.jumps[moved.clone()]);
+            let between_leaves = count_leaves(&self.jumps[moved.end..next.start]);
+            self.jumps[moved.start..next.start].rotate_left(moved.len());
+            self.leaves[leaf_start..leaf_start + moved_leaves + between_leaves].rotate_left(moved_leaves);
+            leaf_start += moved_leaves + between_leaves;
+            moved = next;
+        }
+        self.jumps.insert(child_jumps.start, rest);
+        self.relink_jumps();
+    }
+
+    /// Joins the jump at `jump_index`, whose major node has no leaf and one child, with the
+    /// child's jump. Every jump below the child moves up a level.
+    fn merge_only_child(&mut self, jump_index: usize) {
+        let child_jump_index = self.jumps[jump_index].next_major_node.child_jump_index().expect("tree corrupted");
+        let child_jump = self.jumps[child_jump_index];
+        let child_jumps = self.child_jump_range(child_jump_index);
+
+        // The merged jump takes the child's leaf, which goes before the leaves of the jumps in
+        // between.
+        let mut leaf_start = self.leaf_position(child_jump_index + 1);
+        if let Some(leaf_index) = child_jump.next_major_node.leaf_index() {
+            let merged_leaf_index = self.leaf_position(jump_index + 1);
+            self.leaves[merged_leaf_index..leaf_index + 1].rotate_right(1);
+        }
+
+        self.unlink_jumps();
+        let child_major_node = self.jumps[child_jump_index].next_major_node;
+        let jump = &mut self.jumps[jump_index];
+        jump.next_major_node_dist = (child_jump.jump_to_node + child_jump.next_major_node_dist as isize - jump.jump_to_node) as usize;
+        jump.next_major_node = child_major_node;
+
+        // Each level's jumps below the child move to where the level above's were, before the
+        // jumps in between.
+        let mut between_start = child_jump_index + 1;
+        let mut moved = child_jumps;
+        while !moved.is_empty() {
+            let next = self.next_level(moved.clone());
+            let between_leaves = count_leaves(&self.jumps[between_start..moved.start]);
+            let moved_leaves = count_leaves(&self.jumps[moved.clone()]);
+            self.jumps[between_start..moved.end].rotate_right(moved.len());
+            self.leaves[leaf_start..leaf_start + between_leaves + moved_leaves].rotate_right(moved_leaves);
+            leaf_start += between_leaves + moved_leaves;
+            between_start = moved.end;
+            moved = next;
+        }
+        self.jumps.remove(child_jump_index);
+        self.relink_jumps();
+    }
+
+    /// Removes the jumps in `removed`, which have to be next to each other on one level, along
+    /// with every jump below them and all of their leaves. The jumps have to be unlinked.
+    fn remove_jump_subtrees(&mut self, mut removed: Range<usize>) {
+        if removed.is_empty() {
+            return;
+        }
+
+        // Kept jumps and leaves are moved back over the removed ones, and the removed leaves end
+        // up past the kept ones to be truncated away.
+        let mut jump_write = removed.start;
+        let mut leaf_write = self.leaf_position(removed.start);
+        let mut leaf_read = leaf_write;
+        for jump_read in removed.start..self.jumps.len() {
+            let jump = self.jumps[jump_read];
+            let has_leaf = jump.next_major_node.leaf_index().is_some() as usize;
+            if !removed.contains(&jump_read) {
+                self.jumps[jump_write] = jump;
+                jump_write += 1;
+                if has_leaf == 1 {
+                    self.leaves.swap(leaf_write, leaf_read);
+                    leaf_write += 1;
                 }
             }
+            leaf_read += has_leaf;
 
-            {
-                let jump = self.jumps[jump_index];
-                let leaf_jump = self.jumps[leaf_jump_index];
-                match self.jumps[jump_index].next_major_node {
-                    MajorNode::LeafJump{ref mut leaf_index, ..} => {
-                        match jump < leaf_jump {
-                            true => leaf_insert_index = *leaf_index + 1,
-                            false => *leaf_index += 1
-                        }
-                    },
-                    MajorNode::Leaf{ref mut leaf_index} if *leaf_index != -1 && leaf_opt.is_some() => {
-                        match jump < leaf_jump {
-                            true => leaf_insert_index = (*leaf_index + 1) as usize,
-                            false => *leaf_index += 1
-                        }
-                    },
-                    MajorNode::Leaf{..}  |
-                    MajorNode::Jump{..} => ()
-                }
+            if jump_read + 1 == removed.end {
+                removed = self.next_level(removed);
             }
         }
-        self.nodes.splice(insert_node_index..insert_node_index, Some(first_node).into_iter().chain(nodes));
+        self.jumps.truncate(jump_write);
+        self.leaves.truncate(leaf_write);
+    }
 
-        if let Some(leaf) = leaf_opt {
-            match self.jumps[leaf_jump_index].next_major_node {
+    /// Replaces each jump's child jump index with its number of children, so that jumps can be
+    /// inserted, removed and moved between levels without renumbering the rest.
+    fn unlink_jumps(&mut self) {
+        let mut children_end = self.jumps.len();
+        for jump in self.jumps.iter_mut().rev() {
+            match jump.next_major_node {
+                MajorNode::LeafJump{ref mut child_jump_index, ..} |
+                MajorNode::Jump{ref mut child_jump_index} => {
+                    let children_start = *child_jump_index;
+                    *child_jump_index = children_end - children_start;
+                    children_end = children_start;
+                },
+                MajorNode::Leaf{..} => ()
+            }
+        }
+    }
+
+    /// Undoes `unlink_jumps` once the jumps are in breadth-first order again, pointing every jump
+    /// at its first child and back at its parent. Also numbers the leaves in jump order.
+    fn relink_jumps(&mut self) {
+        let mut next_child_jump_index = 1;
+        let mut next_leaf_index = 0;
+        for jump_index in 0..self.jumps.len() {
+            let has_leaf = self.jumps[jump_index].next_major_node.leaf_index().is_some();
+            let child_count = match self.jumps[jump_index].next_major_node {
+                MajorNode::LeafJump{ref mut leaf_index, ref mut child_jump_index} => {
+                    *leaf_index = next_leaf_index;
+                    (*child_jump_index, *child_jump_index = next_child_jump_index).0
+                },
+                MajorNode::Jump{ref mut child_jump_index} => {
+                    (*child_jump_index, *child_jump_index = next_child_jump_index).0
+                },
                 MajorNode::Leaf{ref mut leaf_index} => {
-                    if *leaf_index != -1 {
-                        panic!("unexpected leaf index: {}", leaf_index);
+                    if has_leaf {
+                        *leaf_index = next_leaf_index as isize;
                     }
-                    *leaf_index = leaf_insert_index as isize;
-                    self.leaves.insert(*leaf_index as usize, leaf);
-                },
-                MajorNode::LeafJump{..} |
-                MajorNode::Jump{..} => panic!("Unexpected next jump")
+                    0
+                }
+            };
+            next_leaf_index += has_leaf as usize;
+
+            for child_jump in &mut self.jumps[next_child_jump_index..next_child_jump_index + child_count] {
+                child_jump.parent_jump_index = jump_index as isize;
             }
+            next_child_jump_index += child_count;
         }
+    }
 
-        // Update the parent jump's next major node
+    fn verify_tree_integrity(&self) {
+        #[cfg(debug_assertions)]
         {
-            let parent_jump_mut = &mut self.jumps[cursor.parent_jump_index];
-
-            let jump_inserted = insert_continue_jump || insert_split_jump;
-            if jump_inserted {
-                use std::cmp;
-                let jump_to = cmp::min(insert_continue_jump_index, insert_split_jump_index);
-                parent_jump_mut.next_major_node = match parent_jump_mut.next_major_node {
-                    MajorNode::Jump{..} => parent_jump_mut.next_major_node,
-                    MajorNode::Leaf{leaf_index: -1} => MajorNode::Jump {
-                        child_jump_index: jump_to
-                    },
-                    MajorNode::Leaf{leaf_index} => MajorNode::LeafJump {
-                        leaf_index: leaf_index as usize,
-                        child_jump_index: jump_to
-                    },
-                    MajorNode::LeafJump{child_jump_index, ..} => {
-                        if cursor_at_next_major_node {
-                            parent_jump_mut.next_major_node
-                        } else {
-                            MajorNode::Jump{child_jump_index}
-                        }
-                    }
+            if let Err(e) = self.validate() {
+                panic!("tree corrupted: {}", e);
+            }
+        }
+    }
+
+    /// Iterates over cursors at every node below `cursor`, in pre-order.
+    pub fn descendants(&self, cursor: RawCursor) -> Descendants<'_, N, L> {
+        let mut descendants = Descendants {
+            trie: self,
+            chain: None,
+            jumps: vec![],
+            queued: 0
+        };
+        descendants.enter_children(cursor);
+        descendants
+    }
+
+    /// Removes every node `f` returns `false` for, along with its subtree, in one pass. `f` is
+    /// called in pre-order with the node's ancestors, the node and its leaf, and isn't called for
+    /// nodes inside removed subtrees.
+    pub fn retain<F>(&mut self, mut f: F)
+        where F: FnMut(&[&N], &N, Option<&L>) -> bool
+    {
+        let shapes = self.node_shapes();
+        let mut keep = vec![false; shapes.len()];
+        {
+            let mut ancestors: Vec<&N> = vec![];
+            // Nodes deeper than this are inside a removed subtree.
+            let mut removed_depth = isize::MAX;
+            for (i, (node, shape)) in self.nodes.iter().zip(&shapes).enumerate() {
+                if removed_depth < shape.depth {
+                    continue;
+                }
+                ancestors.truncate(shape.depth as usize);
+                keep[i] = f(&ancestors, node, shape.leaf.map(|l| &self.leaves[l]));
+                removed_depth = match keep[i] {
+                    true => isize::MAX,
+                    false => shape.depth
                 };
+                ancestors.push(node);
             }
-            parent_jump_mut.next_major_node_dist = (!jump_inserted) as usize + (cursor.node_index - parent_jump_mut.jump_to_node) as usize;
         }
 
-        if let Some(range) = children_rotate_range {
-            let jump = self.jumps[range.start];
-            match self.jumps[jump.parent_jump_index as usize].next_major_node {
-                MajorNode::LeafJump{ref mut child_jump_index, ..} |
-                MajorNode::Jump{ref mut child_jump_index} => *child_jump_index += (children_rotate_to - range.start) - range.len(),
-                MajorNode::Leaf{..} => panic!("tree corrupted")
+        if keep.iter().all(|&keep| keep) {
+            return;
+        }
+        let leaf_pool = self.leaves.drain(..).map(Some).collect();
+        self.rebuild_retaining(&shapes, &keep, leaf_pool);
+    }
+
+    /// Removes every leaf `f` returns `false` for, keeping all the nodes.
+    pub fn retain_leaves<F>(&mut self, mut f: F)
+        where F: FnMut(&L) -> bool
+    {
+        let shapes = self.node_shapes();
+        let leaf_pool: Vec<Option<L>> = self.leaves.drain(..).map(|l| Some(l).filter(&mut f)).collect();
+        self.rebuild_retaining(&shapes, &vec![true; shapes.len()], leaf_pool);
+    }
+
+    /// Maps every node and leaf, keeping the jumps as they are.
+    ///
+    /// # Panics
+    /// Panics if `map_node` maps two siblings to equal nodes.
+    pub fn try_map<N2, L2, E, F, G>(self, map_node: F, map_leaf: G) -> Result<RawTrie<N2, L2>, E>
+        where N2: Eq,
+              F: FnMut(&N) -> Result<N2, E>,
+              G: FnMut(L) -> Result<L2, E>
+    {
+        let nodes: Vec<N2> = self.nodes.iter().map(map_node).collect::<Result<_, E>>()?;
+        let leaves: Vec<L2> = self.leaves.into_vec().into_iter().map(map_leaf).collect::<Result<_, E>>()?;
+
+        // Only nodes that start a jump can have siblings, and jumps with the same parent are next
+        // to each other.
+        let mut siblings_start = 1;
+        for (i, jump) in self.jumps.iter().enumerate().skip(1) {
+            if jump.parent_jump_index != self.jumps[i - 1].parent_jump_index {
+                siblings_start = i;
+            }
+            let node = &nodes[jump.jump_to_node as usize];
+            if self.jumps[siblings_start..i].iter().any(|sibling| nodes[sibling.jump_to_node as usize] == *node) {
+                panic!("Mapped two siblings to equal nodes");
             }
+        }
+
+        Ok(RawTrie {
+            nodes: nodes.into(),
+            jumps: self.jumps,
+            leaves: leaves.into()
+        })
+    }
+
+    /// Maps every leaf, removing the ones `f` returns `None` for, along with every subtree left
+    /// without any leaves.
+    pub fn filter_map_leaves<L2, F>(self, f: F) -> RawTrie<N, L2>
+        where F: FnMut(L) -> Option<L2>
+    {
+        let shapes = self.node_shapes();
+        let leaf_pool = self.leaves.into_vec().into_iter().map(f).collect();
+        let mut trie = RawTrie {
+            nodes: self.nodes,
+            jumps: self.jumps,
+            leaves: vec![].into()
+        };
+        trie.rebuild_without_leafless(&shapes, leaf_pool);
+        trie
+    }
 
-            self.jumps[range.start..children_rotate_to].rotate(range.len());
+    /// Checks every invariant the rest of `RawTrie` relies on.
+    pub fn validate(&self) -> Result<(), IntegrityError> {
+        match self.jumps.first() {
+            Some(root) if root.parent_jump_index == -1 && root.jump_to_node == -1 && root.depth == -1 => {
+                // A jump's leaf belongs to its major node, which for a root jump without any nodes
+                // is the root.
+                if root.next_major_node_dist == 0 && root.next_major_node.leaf_index().is_some() {
+                    return Err(IntegrityError::MissingRoot);
+                }
+            },
+            _ => return Err(IntegrityError::MissingRoot)
         }
 
-        self.verify_tree_integrity();
+        let mut next_leaf_index = 0;
+        for (i, jump) in self.jumps.iter().enumerate() {
+            if 0 < i && self.jumps[i - 1] >= *jump {
+                return Err(IntegrityError::UnsortedJump(i));
+            }
 
-        RawCursor {
-            node_index: (insert_node_index + num_nodes_insert - 1) as isize,
-            parent_jump_index: match self.jumps[cursor.parent_jump_index].next_major_node {
-                MajorNode::Leaf{..} => cursor.parent_jump_index,
-                MajorNode::LeafJump{child_jump_index, ..} |
-                MajorNode::Jump{child_jump_index} => {
-                    if insert_split_jump {
-                        insert_split_jump_index
-                    } else {
-                        child_jump_index
+            let node_range_valid =
+                (i == 0 || 0 <= jump.jump_to_node) &&
+                jump.next_major_node_dist <= self.nodes.len() &&
+                jump.jump_to_node.checked_add(jump.next_major_node_dist as isize).is_some_and(|n| n < self.nodes.len() as isize);
+            if !node_range_valid {
+                return Err(IntegrityError::BadNodeRange(i));
+            }
+
+            if 0 < i {
+                let parent_jump = match 0 <= jump.parent_jump_index && jump.parent_jump_index < i as isize {
+                    true => self.jumps[jump.parent_jump_index as usize],
+                    false => return Err(IntegrityError::BadParent(i))
+                };
+                // Jumps are sorted by parent, so if the parent's first child is at or before this
+                // jump, every jump in between shares this jump's parent.
+                match parent_jump.next_major_node.child_jump_index() {
+                    Some(child_jump_index) if child_jump_index <= i => (),
+                    _ => return Err(IntegrityError::BadParent(i))
+                }
+                if jump.depth != parent_jump.depth + parent_jump.next_major_node_dist as isize + 1 {
+                    return Err(IntegrityError::BadDepth(i));
+                }
+            }
+
+            if let Some(child_jump_index) = jump.next_major_node.child_jump_index() {
+                let is_first_child =
+                    i < child_jump_index &&
+                    self.jumps.get(child_jump_index).map(|j| j.parent_jump_index) == Some(i as isize) &&
+                    self.jumps[child_jump_index - 1].parent_jump_index != i as isize;
+                if !is_first_child {
+                    return Err(IntegrityError::BadChild(i));
+                }
+            }
+
+            if let Some(leaf_index) = jump.next_major_node.leaf_index() {
+                if leaf_index != next_leaf_index {
+                    return Err(IntegrityError::BadLeafIndex(i));
+                }
+                next_leaf_index += 1;
+            }
+        }
+        if next_leaf_index != self.leaves.len() {
+            return Err(IntegrityError::UnusedLeaves);
+        }
+
+        // Nodes are stored in pre-order, so each jump's first child has to start right after the
+        // jump's major node, and every other child right after the previous child's subtree. Walk
+        // the jumps backwards so that children are visited before their parents.
+        let mut subtree_ends = vec![0; self.jumps.len()];
+        for (i, jump) in self.jumps.iter().enumerate().rev() {
+            let mut child_start = match jump.jump_to_node.checked_add(jump.next_major_node_dist as isize + 1) {
+                Some(child_start) => child_start,
+                None => return Err(IntegrityError::BadNodeRange(i))
+            };
+            if let Some(first_child_jump_index) = jump.next_major_node.child_jump_index() {
+                let children = self.jumps[first_child_jump_index..].iter().zip(first_child_jump_index..)
+                    .take_while(|&(j, _)| j.parent_jump_index == i as isize);
+                for (child_jump, child_jump_index) in children {
+                    if child_jump.jump_to_node != child_start {
+                        return Err(IntegrityError::BadNodeRange(child_jump_index));
+                    }
+                    child_start = subtree_ends[child_jump_index];
+
+                    let child_node = &self.nodes[child_jump.jump_to_node as usize];
+                    let is_duplicate = self.jumps[first_child_jump_index..child_jump_index].iter()
+                        .any(|sibling| self.nodes[sibling.jump_to_node as usize] == *child_node);
+                    if is_duplicate {
+                        return Err(IntegrityError::DuplicateSibling(child_jump_index));
                     }
                 }
-            },
-            depth: cursor.depth + num_nodes_insert as isize
+            }
+            subtree_ends[i] = child_start;
+        }
+        match subtree_ends[0] == self.nodes.len() as isize {
+            true => Ok(()),
+            false => Err(IntegrityError::UnusedNodes)
         }
     }
+}
 
-    pub fn prune_node(&mut self, cursor: RawCursor) {
-        if cursor == RawCursor::root() {
-            self.nodes.clear();
-            self.jumps.clear();
-            self.jumps.extend(Some(Jump::default_root()));
-            self.leaves.clear();
-        } else {
-            let last_child_node = self.last_child_node(cursor);
+impl<N: Eq, L> Default for PreorderBuilder<N, L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-            let parent_jump = self.jumps[cursor.parent_jump_index];
-            let first_child_jump_index = match parent_jump.next_major_node {
-                MajorNode::LeafJump{child_jump_index, ..} |
-                MajorNode::Jump{child_jump_index} => child_jump_index,
-                MajorNode::Leaf{..} => usize::max_value()
+#[cfg_attr(not(any(feature = "serde", feature = "fuzzing")), allow(dead_code))]
+impl<N: Eq, L> PreorderBuilder<N, L> {
+    pub fn new() -> PreorderBuilder<N, L> {
+        PreorderBuilder {
+            nodes: vec![],
+            shapes: vec![],
+            leaf_pool: vec![]
+        }
+    }
+
+    pub fn node(&self, node_index: usize) -> &N {
+        &self.nodes[node_index]
+    }
+
+    /// Appends a node to the listing and returns its index. Top-level nodes have a depth of `0`.
+    ///
+    /// # Panics
+    /// Panics if the node would be more than one level deeper than the previous node.
+    pub fn push_node(&mut self, depth: usize, node: N) -> usize {
+        let max_depth = self.shapes.last().map(|s| s.depth + 1).unwrap_or(0);
+        if max_depth < depth as isize {
+            panic!("node depth skips a level");
+        }
+
+        self.nodes.push(node);
+        self.shapes.push(NodeShape {
+            depth: depth as isize,
+            leaf: None
+        });
+        self.nodes.len() - 1
+    }
+
+    pub fn set_leaf(&mut self, node_index: usize, leaf: L) {
+        self.leaf_pool.push(Some(leaf));
+        self.shapes[node_index].leaf = Some(self.leaf_pool.len() - 1);
+    }
+
+    pub fn build(self) -> RawTrie<N, L> {
+        let mut trie = RawTrie {
+            nodes: self.nodes.into(),
+            jumps: vec![].into(),
+            leaves: vec![].into()
+        };
+        trie.rebuild_jumps(&self.shapes, self.leaf_pool);
+        trie
+    }
+}
+
+impl<N: Eq, L> EditTree<N, L> {
+    /// Makes sure every node along `path` exists, inserting missing nodes after their existing
+    /// siblings. If `leaf` is `Some`, it replaces the last node's leaf.
+    ///
+    /// # Panics
+    /// Panics if `path` is empty and `leaf` is `Some`, since the root can't have a leaf.
+    pub fn insert<I>(&mut self, path: I, leaf: Option<L>)
+        where I: IntoIterator<Item=N>
+    {
+        let mut parent = 0;
+        for node in path {
+            let existing = self.nodes[parent].children.iter().cloned()
+                .find(|&c| self.nodes[c].node.as_ref() == Some(&node));
+            parent = match existing {
+                Some(child) => child,
+                None => {
+                    let child = self.nodes.len();
+                    self.nodes.push(EditNode {
+                        node: Some(node),
+                        leaf: None,
+                        children: vec![]
+                    });
+                    self.nodes[parent].children.push(child);
+                    child
+                }
             };
+        }
 
-            let parent_leaf_index: isize;
+        if let Some(leaf) = leaf {
+            if parent == 0 {
+                panic!("Attempted to give the root a leaf");
+            }
+            self.leaf_pool.push(Some(leaf));
+            self.nodes[parent].leaf = Some(self.leaf_pool.len() - 1);
+        }
+    }
 
-            {
-                let parent_jump_mut = &mut self.jumps[cursor.parent_jump_index];
-                parent_leaf_index = match parent_jump_mut.next_major_node {
-                    MajorNode::Leaf{leaf_index} => leaf_index,
-                    MajorNode::LeafJump{leaf_index, ..} => leaf_index as isize,
-                    MajorNode::Jump{..} => -1
-                };
+    /// Removes the node at the end of `path` and all of its descendants, if it exists. An empty
+    /// path removes every node.
+    pub fn prune(&mut self, path: &[N]) {
+        let (last, init) = match path.split_last() {
+            Some(split) => split,
+            None => {
+                self.nodes[0].children.clear();
+                return;
+            }
+        };
 
-                parent_jump_mut.next_major_node = MajorNode::Leaf{ leaf_index: -1 };
-                parent_jump_mut.next_major_node_dist = ((cursor.node_index - 1) - parent_jump.jump_to_node) as usize;
+        let mut parent = 0;
+        for node in init {
+            match self.child(parent, node) {
+                Some(child) => parent = child,
+                None => return
             }
+        }
+        if let Some(child) = self.child(parent, last) {
+            self.nodes[parent].children.retain(|&c| c != child);
+        }
+    }
+
+    fn child(&self, parent: usize, node: &N) -> Option<usize> {
+        self.nodes[parent].children.iter().cloned()
+            .find(|&c| self.nodes[c].node.as_ref() == Some(node))
+    }
+}
+
+impl<N: Eq, L> RawTrie<N, L> {
+    /// Unpacks the trie into an `EditTree`, lets `edit` change it, then packs it back and rebuilds
+    /// the jumps once. Node and leaf order is the same as making each edit on the trie directly.
+    pub fn edit<F, R>(&mut self, edit: F) -> R
+        where F: FnOnce(&mut EditTree<N, L>) -> R
+    {
+        let shapes = self.node_shapes();
+        let mut tree = EditTree {
+            nodes: Vec::with_capacity(self.nodes.len() + 1),
+            leaf_pool: self.leaves.drain(..).map(Some).collect()
+        };
+        tree.nodes.push(EditNode {
+            node: None,
+            leaf: None,
+            children: vec![]
+        });
+
+        // `ancestors[d]` is the most recent node at depth `d - 1`, with the root at `0`.
+        let mut ancestors = vec![0];
+        for (node, shape) in self.nodes.drain(..).zip(&shapes) {
+            let index = tree.nodes.len();
+            ancestors.truncate(shape.depth as usize + 1);
+            tree.nodes[*ancestors.last().unwrap()].children.push(index);
+            tree.nodes.push(EditNode {
+                node: Some(node),
+                leaf: shape.leaf,
+                children: vec![]
+            });
+            ancestors.push(index);
+        }
 
+        let result = edit(&mut tree);
 
-            self.nodes.splice(cursor.node_index as usize..last_child_node.node_index as usize + 1, None);
+        let mut shapes = Vec::with_capacity(tree.nodes.len());
+        let mut stack: Vec<(usize, isize)> = tree.nodes[0].children.iter().rev().map(|&c| (c, 0)).collect();
+        while let Some((index, depth)) = stack.pop() {
+            let edit_node = &mut tree.nodes[index];
+            self.nodes.push(edit_node.node.take().expect("node linked twice"));
+            shapes.push(NodeShape {
+                depth: depth,
+                leaf: edit_node.leaf
+            });
+            stack.extend(edit_node.children.iter().rev().map(|&c| (c, depth + 1)));
+        }
 
-            let nodes_removed = (last_child_node.node_index + 1) - cursor.node_index;
-            let remove_parent_jump_index = match cursor.node_index == parent_jump.jump_to_node {
-                true => cursor.parent_jump_index as isize,
-                false => isize::max_value()
-            };
+        self.rebuild_jumps(&shapes, tree.leaf_pool);
+        result
+    }
 
-            let mut retain_index = 0;
-            let mut child_jump_count = 0;
-            let mut leaf_remove_range = 0usize..0;
-            self.jumps.retain_mut(|jump| {
-                let in_child_jump_range =
-                    first_child_jump_index <= retain_index &&
-                    jump.jump_to_node <= last_child_node.node_index;
-                let retain = !(
-                    in_child_jump_range ||
-                    retain_index == cursor.parent_jump_index && cursor.node_index == parent_jump.jump_to_node
-                );
-
-                if last_child_node.node_index <= jump.jump_to_node {
-                    jump.jump_to_node -= nodes_removed;
-                }
+    /// Drops every node without a leaf from `leaf_pool` in its subtree, then rebuilds the jumps
+    /// from the nodes that are left. `shapes` describes `nodes` before anything is dropped.
+    fn rebuild_without_leafless(&mut self, shapes: &[NodeShape], leaf_pool: Vec<Option<L>>) {
+        let has_leaf = |shape: &NodeShape| shape.leaf.map(|i| leaf_pool[i].is_some()).unwrap_or(false);
+
+        // Walking backwards visits children before their parents. `live[d]` is whether a node at
+        // depth `d` has been kept since its parent was last visited.
+        let mut keep = vec![false; shapes.len()];
+        let mut live: Vec<bool> = vec![];
+        for (i, shape) in shapes.iter().enumerate().rev() {
+            let depth = shape.depth as usize;
+            if live.len() < depth + 2 {
+                live.resize(depth + 2, false);
+            }
+            keep[i] = has_leaf(shape) || live[depth + 1];
+            live[depth + 1] = false;
+            live[depth] |= keep[i];
+        }
 
-                {
-                    let jump_offset = |jump_index: isize| {
-                        (remove_parent_jump_index < jump_index) as usize +
-                        match first_child_jump_index <= retain_index && last_child_node.node_index < jump_index {
-                            false => 0,
-                            true => child_jump_count
-                        }
-                    };
+        self.rebuild_retaining(shapes, &keep, leaf_pool);
+    }
 
-                    jump.parent_jump_index -= jump_offset(jump.parent_jump_index) as isize;
-                    match jump.next_major_node {
-                        MajorNode::Jump{ref mut child_jump_index} => {
-                            *child_jump_index -= jump_offset(*child_jump_index as isize);
-                        }
-                        MajorNode::Leaf{ref mut leaf_index} if *leaf_index != -1 => {
-                            if parent_leaf_index != -1 && parent_leaf_index < *leaf_index {
-                                *leaf_index -= 1;
-                            }
-                            if retain_index == cursor.parent_jump_index {
-                                leaf_remove_range.start = *leaf_index as usize;
-                                leaf_remove_range.end = leaf_remove_range.start;
-                            } else if retain_index < first_child_jump_index {
-                                // This branch is before the child jump range
-                                leaf_remove_range.start = (*leaf_index + 1) as usize;
-                                leaf_remove_range.end = leaf_remove_range.start;
-                            } else if jump.jump_to_node <= last_child_node.node_index {
-                                // This branch is during the child jump range
-                                leaf_remove_range.end = (*leaf_index + 1) as usize;
-                            } else {
-                                // This branch is after the child jump range
-                                *leaf_index -= leaf_remove_range.len() as isize;
-                            }
-                        },
-                        MajorNode::LeafJump{ref mut child_jump_index, ref mut leaf_index} => {
-                            *child_jump_index -= jump_offset(*child_jump_index as isize);
-                            if parent_leaf_index != -1 && (parent_leaf_index as usize) < *leaf_index {
-                                *leaf_index -= 1;
-                            }
-                            if retain_index + 1 == cursor.parent_jump_index {
-                                leaf_remove_range.start = *leaf_index;
-                                leaf_remove_range.end = leaf_remove_range.start;
-                            } else if retain_index < first_child_jump_index {
-                                // This branch is before the child jump range
-                                leaf_remove_range.start = *leaf_index + 1;
-                                leaf_remove_range.end = leaf_remove_range.start;
-                            } else if jump.jump_to_node <= last_child_node.node_index {
-                                // This branch is during the child jump range
-                                leaf_remove_range.end = *leaf_index + 1;
-                            } else {
-                                // This branch is after the child jump range
-                                *leaf_index -= leaf_remove_range.len();
-                            }
-                        }
-                        MajorNode::Leaf{..} => ()
+    /// Drops the nodes in `nodes` that `keep` is `false` for, and the leaves missing from
+    /// `leaf_pool`, then rebuilds the jumps. `shapes` describes `nodes` before anything is dropped.
+    fn rebuild_retaining(&mut self, shapes: &[NodeShape], keep: &[bool], leaf_pool: Vec<Option<L>>) {
+        let mut kept = keep.iter();
+        self.nodes.retain(|_| *kept.next().unwrap());
+        let shapes: Vec<NodeShape> = shapes.iter().zip(keep)
+            .filter(|&(_, &keep)| keep)
+            .map(|(shape, _)| NodeShape {
+                depth: shape.depth,
+                leaf: shape.leaf.filter(|&i| leaf_pool[i].is_some())
+            })
+            .collect();
+        self.rebuild_jumps(&shapes, leaf_pool);
+    }
+
+    /// Replaces `jumps` and `leaves` with ones describing `shapes`, which lists the shape of every
+    /// node in `nodes`. Each shape's `leaf` is an index into `leaf_pool`.
+    fn rebuild_jumps(&mut self, shapes: &[NodeShape], mut leaf_pool: Vec<Option<L>>) {
+        debug_assert_eq!(self.nodes.len(), shapes.len());
+
+        // `subtree_ends[i]` is the index one past the last descendant of node `i`.
+        let mut subtree_ends = vec![shapes.len(); shapes.len()];
+        let mut ancestors: Vec<usize> = vec![];
+        for (i, shape) in shapes.iter().enumerate() {
+            while let Some(&ancestor) = ancestors.last() {
+                match shapes[ancestor].depth < shape.depth {
+                    true => break,
+                    false => {
+                        subtree_ends[ancestor] = i;
+                        ancestors.pop();
                     }
                 }
+            }
+            ancestors.push(i);
+        }
 
-                if in_child_jump_range {
-                    child_jump_count += 1
+        // These treat `-1` as the root node.
+        let subtree_end = |i: isize| match i {
+            -1 => shapes.len(),
+            _ => subtree_ends[i as usize]
+        };
+        let leaf = |i: isize| match i {
+            -1 => None,
+            _ => shapes[i as usize].leaf
+        };
+
+        self.jumps.clear();
+        self.jumps.push(Jump::default_root());
+        self.leaves.clear();
+
+        // Jumps are pushed breadth-first, so every jump's children end up sorted after it and next
+        // to each other.
+        let mut jump_index = 0;
+        while jump_index < self.jumps.len() {
+            let jump_to_node = self.jumps[jump_index].jump_to_node;
+
+            // Follow the chain until hitting a node that has a leaf or doesn't have exactly one child.
+            let mut major_node = jump_to_node;
+            loop {
+                let child = major_node + 1;
+                let has_one_child =
+                    (child as usize) < subtree_end(major_node) &&
+                    subtree_end(child) == subtree_end(major_node);
+                if leaf(major_node).is_some() || !has_one_child {
+                    break;
                 }
-                retain_index += 1;
+                major_node = child;
+            }
 
-                retain
+            let leaf_index_opt = leaf(major_node).map(|i| {
+                self.leaves.push(leaf_pool[i].take().expect("leaf used twice"));
+                self.leaves.len() - 1
             });
-            self.leaves.splice(leaf_remove_range, None);
-            if parent_leaf_index != -1 {
-                self.leaves.remove(parent_leaf_index as usize);
+
+            let child_jump_index = self.jumps.len();
+            let mut child = (major_node + 1) as usize;
+            while child < subtree_end(major_node) {
+                self.jumps.push(Jump {
+                    depth: shapes[child].depth,
+                    parent_jump_index: jump_index as isize,
+                    jump_to_node: child as isize,
+                    next_major_node_dist: 0,
+                    next_major_node: MajorNode::Leaf{ leaf_index: -1 }
+                });
+                child = subtree_ends[child];
             }
-            self.verify_tree_integrity();
+            let has_children = child_jump_index < self.jumps.len();
+
+            let jump = &mut self.jumps[jump_index];
+            jump.next_major_node_dist = (major_node - jump_to_node) as usize;
+            jump.next_major_node = match (leaf_index_opt, has_children) {
+                (Some(leaf_index), true) => MajorNode::LeafJump {
+                    leaf_index: leaf_index,
+                    child_jump_index: child_jump_index
+                },
+                (None, true) => MajorNode::Jump{ child_jump_index: child_jump_index },
+                (Some(leaf_index), false) => MajorNode::Leaf{ leaf_index: leaf_index as isize },
+                (None, false) => MajorNode::Leaf{ leaf_index: -1 }
+            };
+            jump_index += 1;
         }
+
+        self.verify_tree_integrity();
     }
+}
 
-    fn verify_tree_integrity(&self) {
-        #[cfg(debug_assertions)]
-        {
-            // Sanity check to see if the jump list is sorted.
-            assert!(self.jumps.windows(2).all(|x| x[0] < x[1]));
-            let mut leaves_used = vec![];
-            for (i, jump) in self.jumps.iter().enumerate() {
-                match jump.next_major_node {
-                    MajorNode::LeafJump{child_jump_index, ..} |
-                    MajorNode::Jump{child_jump_index} => assert_eq!(i, self.jumps[child_jump_index].parent_jump_index as usize),
-                    _ => ()
-                }
+impl<'a, N: Eq, L> Descendants<'a, N, L> {
+    /// Queues up the children of the node at `cursor`, which has just been visited.
+    fn enter_children(&mut self, cursor: RawCursor) {
+        self.queued = 0;
+        let jump = self.trie.jumps[cursor.parent_jump_index];
+        if !jump.cursor_at_next_major_node(cursor) {
+            self.chain = Some(RawCursor {
+                node_index: cursor.node_index + 1,
+                parent_jump_index: cursor.parent_jump_index,
+                depth: cursor.depth + 1
+            });
+        } else if let Some(child_jump_index) = jump.next_major_node.child_jump_index() {
+            let child_count = self.trie.jumps[child_jump_index..].iter()
+                .take_while(|j| j.parent_jump_index == cursor.parent_jump_index as isize)
+                .count();
+            self.jumps.extend((child_jump_index..child_jump_index + child_count).rev());
+            self.queued = child_count;
+        }
+    }
 
-                match jump.next_major_node {
-                    MajorNode::LeafJump{leaf_index, ..} => leaves_used.push(leaf_index),
-                    MajorNode::Leaf{leaf_index} if leaf_index != -1 => leaves_used.push(leaf_index as usize),
-                    _ => ()
+    /// Skips the nodes below the one `next` last returned.
+    pub fn skip_subtree(&mut self) {
+        self.chain = None;
+        self.jumps.truncate(self.jumps.len() - self.queued);
+        self.queued = 0;
+    }
+}
+
+impl<'a, N: Eq, L> Iterator for Descendants<'a, N, L> {
+    type Item = RawCursor;
+
+    fn next(&mut self) -> Option<RawCursor> {
+        let cursor = match self.chain.take() {
+            Some(cursor) => cursor,
+            None => {
+                let jump_index = self.jumps.pop()?;
+                let jump = self.trie.jumps[jump_index];
+                RawCursor {
+                    node_index: jump.jump_to_node,
+                    parent_jump_index: jump_index,
+                    depth: jump.depth
                 }
             }
-
-            assert!(leaves_used.windows(2).all(|x| x[0] < x[1]));
-        }
+        };
+        self.enter_children(cursor);
+        Some(cursor)
     }
 }
 
+/// The number of jumps in `jumps` whose major node has a leaf.
+fn count_leaves(jumps: &[Jump]) -> usize {
+    jumps.iter().filter(|j| j.next_major_node.leaf_index().is_some()).count()
+}
+
 impl Jump {
     #[inline]
     fn default_root() -> Jump {
@@ -747,6 +1740,35 @@ impl Jump {
             next_major_node: MajorNode::Leaf{ leaf_index: -1 }
         }
     }
+    fn to_record(self) -> JumpRecord {
+        JumpRecord {
+            parent_jump_index: self.parent_jump_index,
+            jump_to_node: self.jump_to_node,
+            next_major_node_dist: self.next_major_node_dist,
+            depth: self.depth,
+            leaf_index: self.next_major_node.leaf_index(),
+            child_jump_index: self.next_major_node.child_jump_index()
+        }
+    }
+
+    fn from_record(record: JumpRecord) -> Jump {
+        Jump {
+            parent_jump_index: record.parent_jump_index,
+            jump_to_node: record.jump_to_node,
+            next_major_node_dist: record.next_major_node_dist,
+            depth: record.depth,
+            next_major_node: match (record.leaf_index, record.child_jump_index) {
+                (Some(leaf_index), Some(child_jump_index)) => MajorNode::LeafJump {
+                    leaf_index: leaf_index,
+                    child_jump_index: child_jump_index
+                },
+                (None, Some(child_jump_index)) => MajorNode::Jump{ child_jump_index: child_jump_index },
+                (Some(leaf_index), None) => MajorNode::Leaf{ leaf_index: leaf_index as isize },
+                (None, None) => MajorNode::Leaf{ leaf_index: -1 }
+            }
+        }
+    }
+
     #[inline]
     fn cursor_at_next_major_node(&self, cursor: RawCursor) -> bool {
         let cursor_dist = cursor.node_index - self.jump_to_node;
@@ -772,6 +1794,11 @@ impl RawCursor {
     pub fn depth(self) -> isize {
         self.depth
     }
+
+    /// The index of the cursor's node in `nodes`, or `-1` at the root.
+    pub fn node_index(self) -> isize {
+        self.node_index
+    }
 }
 
 impl MajorNode {
@@ -783,4 +1810,49 @@ impl MajorNode {
             MajorNode::Leaf{..} => false
         }
     }
+
+    #[inline]
+    fn child_jump_index(self) -> Option<usize> {
+        match self {
+            MajorNode::LeafJump{child_jump_index, ..} |
+            MajorNode::Jump{child_jump_index} => Some(child_jump_index),
+            MajorNode::Leaf{..} => None
+        }
+    }
+
+    #[inline]
+    fn leaf_index(self) -> Option<usize> {
+        match self {
+            MajorNode::Leaf{leaf_index: -1} |
+            MajorNode::Jump{..} => None,
+            MajorNode::LeafJump{leaf_index, ..} => Some(leaf_index),
+            MajorNode::Leaf{leaf_index} => Some(leaf_index as usize)
+        }
+    }
 }
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.nodes + self.jumps + self.leaves
+    }
+}
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IntegrityError::MissingRoot => write!(f, "missing root jump"),
+            IntegrityError::UnsortedJump(i) => write!(f, "jump {} is out of order", i),
+            IntegrityError::BadParent(i) => write!(f, "jump {} has an invalid parent", i),
+            IntegrityError::BadChild(i) => write!(f, "jump {} doesn't point to its first child", i),
+            IntegrityError::BadDepth(i) => write!(f, "jump {} has the wrong depth", i),
+            IntegrityError::BadNodeRange(i) => write!(f, "jump {} has an invalid node range", i),
+            IntegrityError::BadLeafIndex(i) => write!(f, "jump {} has an out-of-order leaf index", i),
+            IntegrityError::DuplicateSibling(i) => write!(f, "jump {} duplicates a sibling node", i),
+            IntegrityError::UnusedLeaves => write!(f, "some leaves aren't used by any jump"),
+            IntegrityError::UnusedNodes => write!(f, "some nodes aren't covered by any jump")
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for IntegrityError {}
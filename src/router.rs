@@ -0,0 +1,188 @@
+//! `Router`, which matches `/`-separated paths against routes with `:param` placeholders.
+
+use super::{FlatTrie, Cursor, Entry};
+use crate::keys;
+
+use core::fmt;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+/// One segment of a route in a `Router`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RouteSegment {
+    /// Matches a path segment equal to this one.
+    Literal(Box<str>),
+    /// Matches any one path segment, and captures it under this name. Written `:name` in routes.
+    Param(Box<str>)
+}
+
+impl RouteSegment {
+    fn parse(segment: &str) -> RouteSegment {
+        match segment.strip_prefix(':') {
+            Some(name) => RouteSegment::Param(name.into()),
+            None => RouteSegment::Literal(segment.into())
+        }
+    }
+}
+
+impl fmt::Display for RouteSegment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RouteSegment::Literal(ref literal) => f.write_str(literal),
+            RouteSegment::Param(ref name) => write!(f, ":{}", name)
+        }
+    }
+}
+
+/// The parameters captured by matching a path against a route, in the order they appear in the
+/// path.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Params<'r, 'p> {
+    captures: Vec<(&'r str, &'p str)>
+}
+
+impl<'r, 'p> Params<'r, 'p> {
+    /// The path segment captured by the parameter `name`.
+    pub fn get(&self, name: &str) -> Option<&'p str> {
+        self.captures.iter().find(|&&(n, _)| n == name).map(|&(_, value)| value)
+    }
+
+    /// Every parameter's name and captured path segment.
+    pub fn iter(&self) -> impl '_ + Iterator<Item=(&'r str, &'p str)> {
+        self.captures.iter().cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.captures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.captures.is_empty()
+    }
+}
+
+/// An HTTP-style router over a trie with one node per route segment, like `/users/:id/posts`.
+///
+/// Routes and paths are split on `/`, ignoring empty segments, so leading, trailing and repeated
+/// slashes don't matter. A segment starting with `:` is a parameter, which matches any one path
+/// segment. Literal segments take priority over parameters: `/users/me` matches a route
+/// `/users/me` before `/users/:id`, but if nothing below the literal matches the rest of the path,
+/// the parameter is tried instead.
+///
+/// Segments aren't percent-decoded.
+#[derive(Debug, Clone)]
+pub struct Router<L>(FlatTrie<RouteSegment, L>);
+
+impl<L> Default for Router<L> {
+    fn default() -> Router<L> {
+        Router::new()
+    }
+}
+
+impl<L> Router<L> {
+    pub fn new() -> Router<L> {
+        Router(FlatTrie::new())
+    }
+
+    /// The underlying trie, for walking it with a `Cursor`.
+    pub fn as_flat(&self) -> &FlatTrie<RouteSegment, L> {
+        &self.0
+    }
+
+    /// Gives `route` the leaf `leaf`, and returns its old leaf.
+    ///
+    /// # Panics
+    /// Panics if `route` has no segments, since the root can't have a leaf, or if it names a
+    /// parameter differently from an existing route at the same position, like `/users/:name`
+    /// after `/users/:id`.
+    pub fn insert(&mut self, route: &str, leaf: L) -> Option<L> {
+        let mut cursor = self.0.cursor();
+        for segment in split(route).map(RouteSegment::parse) {
+            let existing = cursor.child_by(|node| match (node, &segment) {
+                (&RouteSegment::Param(..), &RouteSegment::Param(..)) => true,
+                _ => *node == segment
+            });
+            match existing {
+                Entry::Occupied(child) if *child.node() == segment => {child.enter();},
+                Entry::Occupied(child) => panic!("Route `{}` names parameter `{}`, but another route names it `{}`",
+                                                 route, segment, child.node()),
+                Entry::Vacant(..) => break
+            }
+        }
+        keys::insert(&mut self.0, split(route).map(RouteSegment::parse), leaf)
+    }
+
+    /// Removes and returns `route`'s leaf, along with any segments that were only there for it.
+    /// `route` has to name its parameters the way they were inserted.
+    pub fn remove(&mut self, route: &str) -> Option<L> {
+        keys::remove(&mut self.0, split(route).map(RouteSegment::parse))
+    }
+
+    /// Matches `path` against the routes, and returns the matching route's leaf and the
+    /// parameters it captured.
+    pub fn at<'r, 'p>(&'r self, path: &'p str) -> Option<(&'r L, Params<'r, 'p>)> {
+        let mut params = Params::default();
+        let leaf = route(&mut self.0.cursor(), split(path), &mut params.captures)?;
+        Some((leaf, params))
+    }
+
+    /// The number of routes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+fn split(path: &str) -> impl '_ + Iterator<Item=&str> + Clone {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+type RouteCursor<'r, L> = Cursor<RouteSegment, L, &'r FlatTrie<RouteSegment, L>>;
+
+/// Matches `segments` against the routes below `cursor`, trying the literal child before the
+/// parameter child at each step. Leaves `cursor` where it was if nothing matches.
+fn route<'r, 'p, L, I>(cursor: &mut RouteCursor<'r, L>, mut segments: I, captures: &mut Vec<(&'r str, &'p str)>) -> Option<&'r L>
+    where I: Iterator<Item=&'p str> + Clone
+{
+    // Reading through the trie rather than the cursor gives references that outlive the cursor.
+    let tree: &'r FlatTrie<RouteSegment, L> = cursor.tree;
+    let segment = match segments.next() {
+        Some(segment) => segment,
+        None => return tree.0.get_leaf(cursor.raw)
+    };
+
+    let is_literal = |node: &RouteSegment| match *node {
+        RouteSegment::Literal(ref literal) => **literal == *segment,
+        RouteSegment::Param(..) => false
+    };
+    if let Entry::Occupied(child) = cursor.child_by(is_literal) {
+        let leaf = route(child.enter(), segments.clone(), captures);
+        if leaf.is_some() {
+            return leaf;
+        }
+        cursor.parent().enter();
+    }
+
+    let is_param = |node: &RouteSegment| match *node {
+        RouteSegment::Param(..) => true,
+        RouteSegment::Literal(..) => false
+    };
+    let name = match cursor.child_by(is_param) {
+        Entry::Occupied(child) => match *tree.0.get_node(child.enter().raw).unwrap() {
+            RouteSegment::Param(ref name) => name,
+            RouteSegment::Literal(..) => unreachable!()
+        },
+        Entry::Vacant(..) => return None
+    };
+    captures.push((name, segment));
+    let leaf = route(cursor, segments, captures);
+    if leaf.is_none() {
+        captures.pop();
+        cursor.parent().enter();
+    }
+    leaf
+}
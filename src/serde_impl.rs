@@ -0,0 +1,289 @@
+//! `Serialize` and `Deserialize` support for `FlatTrie`.
+//!
+//! `FlatTrie` serializes as a nested map by default, where every node maps to its leaf and a map
+//! of its children. The `compact` module provides a denser representation that's suited for binary
+//! formats.
+
+use super::FlatTrie;
+use crate::raw::{RawTrie, RawCursor, PreorderBuilder};
+
+use core::fmt::{self, Formatter};
+use core::marker::PhantomData;
+
+use alloc::vec;
+
+use serde::ser::{Serialize, Serializer, SerializeMap, SerializeStruct};
+use serde::de::{self, Deserialize, Deserializer, DeserializeSeed, Visitor, MapAccess, SeqAccess};
+
+const NODE_FIELDS: &[&str] = &["leaf", "children"];
+
+impl<N, L> Serialize for FlatTrie<N, L>
+    where N: Eq + Serialize,
+          L: Serialize
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        NodeChildren {
+            trie: &self.0,
+            cursor: RawCursor::root()
+        }.serialize(serializer)
+    }
+}
+
+impl<'de, N, L> Deserialize<'de> for FlatTrie<N, L>
+    where N: Eq + Deserialize<'de>,
+          L: Deserialize<'de>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut builder = PreorderBuilder::new();
+        ChildrenSeed {
+            builder: &mut builder,
+            depth: 0,
+            _marker: PhantomData
+        }.deserialize(deserializer)?;
+
+        let trie = builder.build();
+        trie.validate().map_err(de::Error::custom)?;
+        Ok(FlatTrie(trie))
+    }
+}
+
+/// Serializes a `FlatTrie` as its raw node, jump and leaf vectors.
+///
+/// Use with `#[serde(with = "flat_trie::compact")]`.
+pub mod compact {
+    use crate::FlatTrie;
+    use crate::raw::RawTrie;
+
+    use serde::ser::{Serialize, Serializer};
+    use serde::de::{self, Deserialize, Deserializer};
+
+    pub fn serialize<N, L, S>(trie: &FlatTrie<N, L>, serializer: S) -> Result<S::Ok, S::Error>
+        where N: Eq + Serialize,
+              L: Serialize,
+              S: Serializer
+    {
+        trie.0.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, N, L, D>(deserializer: D) -> Result<FlatTrie<N, L>, D::Error>
+        where N: Eq + Deserialize<'de>,
+              L: Deserialize<'de>,
+              D: Deserializer<'de>
+    {
+        let trie = RawTrie::deserialize(deserializer)?;
+        trie.validate().map_err(de::Error::custom)?;
+        Ok(FlatTrie(trie))
+    }
+}
+
+struct NodeChildren<'a, N: 'a + Eq, L: 'a> {
+    trie: &'a RawTrie<N, L>,
+    cursor: RawCursor
+}
+
+struct Node<'a, N: 'a + Eq, L: 'a> {
+    trie: &'a RawTrie<N, L>,
+    cursor: RawCursor
+}
+
+impl<'a, N, L> Serialize for NodeChildren<'a, N, L>
+    where N: Eq + Serialize,
+          L: Serialize
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let num_children = self.trie.node_direct_children(self.cursor).count();
+        let mut map = serializer.serialize_map(Some(num_children))?;
+        for child in self.trie.node_direct_children(self.cursor) {
+            map.serialize_entry(self.trie.get_node(child).unwrap(), &Node {
+                trie: self.trie,
+                cursor: child
+            })?;
+        }
+        map.end()
+    }
+}
+
+impl<'a, N, L> Serialize for Node<'a, N, L>
+    where N: Eq + Serialize,
+          L: Serialize
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut node = serializer.serialize_struct("Node", NODE_FIELDS.len())?;
+        node.serialize_field("leaf", &self.trie.get_leaf(self.cursor))?;
+        node.serialize_field("children", &NodeChildren {
+            trie: self.trie,
+            cursor: self.cursor
+        })?;
+        node.end()
+    }
+}
+
+/// Pushes every node in a map of children, and their descendants, onto the builder.
+struct ChildrenSeed<'b, 'de, N: 'b + Eq, L: 'b> {
+    builder: &'b mut PreorderBuilder<N, L>,
+    depth: usize,
+    _marker: PhantomData<&'de ()>
+}
+
+/// Reads the leaf and children of a node that's already been pushed onto the builder.
+struct NodeSeed<'b, 'de, N: 'b + Eq, L: 'b> {
+    builder: &'b mut PreorderBuilder<N, L>,
+    node_index: usize,
+    depth: usize,
+    _marker: PhantomData<&'de ()>
+}
+
+enum NodeField {
+    Leaf,
+    Children
+}
+
+impl<'b, 'de, N, L> DeserializeSeed<'de> for ChildrenSeed<'b, 'de, N, L>
+    where N: Eq + Deserialize<'de>,
+          L: Deserialize<'de>
+{
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'b, 'de, N, L> Visitor<'de> for ChildrenSeed<'b, 'de, N, L>
+    where N: Eq + Deserialize<'de>,
+          L: Deserialize<'de>
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("a map of trie nodes")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut siblings = vec![];
+        while let Some(node) = map.next_key::<N>()? {
+            if siblings.iter().any(|&i| *self.builder.node(i) == node) {
+                return Err(de::Error::custom("duplicate node in trie"));
+            }
+
+            let node_index = self.builder.push_node(self.depth, node);
+            siblings.push(node_index);
+            map.next_value_seed(NodeSeed {
+                builder: &mut *self.builder,
+                node_index: node_index,
+                depth: self.depth,
+                _marker: PhantomData
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl<'b, 'de, N, L> DeserializeSeed<'de> for NodeSeed<'b, 'de, N, L>
+    where N: Eq + Deserialize<'de>,
+          L: Deserialize<'de>
+{
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_struct("Node", NODE_FIELDS, self)
+    }
+}
+
+impl<'b, 'de, N, L> NodeSeed<'b, 'de, N, L>
+    where N: Eq + Deserialize<'de>,
+          L: Deserialize<'de>
+{
+    fn set_leaf(&mut self, leaf: Option<L>) {
+        if let Some(leaf) = leaf {
+            self.builder.set_leaf(self.node_index, leaf);
+        }
+    }
+
+    fn children_seed<'c>(&'c mut self) -> ChildrenSeed<'c, 'de, N, L> {
+        ChildrenSeed {
+            builder: &mut *self.builder,
+            depth: self.depth + 1,
+            _marker: PhantomData
+        }
+    }
+}
+
+impl<'b, 'de, N, L> Visitor<'de> for NodeSeed<'b, 'de, N, L>
+    where N: Eq + Deserialize<'de>,
+          L: Deserialize<'de>
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("a trie node")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        let leaf = seq.next_element::<Option<L>>()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        self.set_leaf(leaf);
+
+        let children_seed = self.children_seed();
+        seq.next_element_seed(children_seed)?
+            .ok_or_else(|| de::Error::invalid_length(1, &"a trie node"))?;
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<(), A::Error> {
+        let (mut has_leaf, mut has_children) = (false, false);
+        while let Some(field) = map.next_key::<NodeField>()? {
+            match field {
+                NodeField::Leaf => {
+                    if has_leaf {
+                        return Err(de::Error::duplicate_field("leaf"));
+                    }
+                    has_leaf = true;
+                    let leaf = map.next_value::<Option<L>>()?;
+                    self.set_leaf(leaf);
+                },
+                NodeField::Children => {
+                    if has_children {
+                        return Err(de::Error::duplicate_field("children"));
+                    }
+                    has_children = true;
+                    let children_seed = self.children_seed();
+                    map.next_value_seed(children_seed)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for NodeField {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<NodeField, D::Error> {
+        struct NodeFieldVisitor;
+
+        impl<'de> Visitor<'de> for NodeFieldVisitor {
+            type Value = NodeField;
+
+            fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+                f.write_str("`leaf` or `children`")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<NodeField, E> {
+                match value {
+                    0 => Ok(NodeField::Leaf),
+                    1 => Ok(NodeField::Children),
+                    _ => Err(de::Error::invalid_value(de::Unexpected::Unsigned(value), &self))
+                }
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<NodeField, E> {
+                match value {
+                    "leaf" => Ok(NodeField::Leaf),
+                    "children" => Ok(NodeField::Children),
+                    _ => Err(de::Error::unknown_field(value, NODE_FIELDS))
+                }
+            }
+        }
+
+        deserializer.deserialize_identifier(NodeFieldVisitor)
+    }
+}
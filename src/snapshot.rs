@@ -0,0 +1,186 @@
+//! `Snapshot`, a read-only handle that shares a `FlatTrie`'s buffers until the trie is next
+//! changed.
+
+use super::{FlatTrie, Cursor};
+use crate::raw::RawCursor;
+
+use core::fmt;
+use core::borrow::Borrow;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// One of a `RawTrie`'s buffers, behind an `Arc` so snapshots can share it. Derefs mutably through
+/// `Arc::make_mut`, which copies the buffer if a `Snapshot` still shares it.
+pub(crate) struct SharedVec<T> {
+    vec: Arc<Vec<T>>,
+    /// `Arc::make_mut`, set by `share`, which is the only way `vec` gets shared. Kept as a function
+    /// pointer so that only `FlatTrie::snapshot` needs `T: Clone`.
+    make_mut: Option<MakeMut<T>>
+}
+
+type MakeMut<T> = fn(&mut Arc<Vec<T>>) -> &mut Vec<T>;
+
+/// A read-only view of a `FlatTrie` as it was when `FlatTrie::snapshot` was called. Taking and
+/// cloning snapshots doesn't copy the trie; the trie copies a buffer the first time it changes it
+/// while a snapshot is alive.
+///
+/// Derefs to `FlatTrie`, and `cursor_owned` gives a `Cursor` that owns the snapshot.
+pub struct Snapshot<N: Eq, L>(FlatTrie<N, L>);
+
+impl<T> SharedVec<T> {
+    /// Another handle to the same buffer. Changing either one afterwards copies the buffer first.
+    pub(crate) fn share(&mut self) -> SharedVec<T>
+        where T: Clone
+    {
+        self.make_mut = Some(Arc::make_mut);
+        SharedVec {
+            vec: self.vec.clone(),
+            make_mut: self.make_mut
+        }
+    }
+
+    /// Another handle to a buffer that `share` already shared, which doesn't need `T: Clone`.
+    pub(crate) fn reshare(&self) -> SharedVec<T> {
+        debug_assert!(self.make_mut.is_some(), "buffer reshared without a snapshot");
+        SharedVec {
+            vec: self.vec.clone(),
+            make_mut: self.make_mut
+        }
+    }
+
+    pub(crate) fn ptr_eq(&self, other: &SharedVec<T>) -> bool {
+        Arc::ptr_eq(&self.vec, &other.vec)
+    }
+
+    /// Removes every element. Keeps the buffer if nothing else shares it, and otherwise starts
+    /// from an empty one rather than copying it first.
+    pub(crate) fn clear(&mut self) {
+        match Arc::get_mut(&mut self.vec) {
+            Some(vec) => vec.clear(),
+            None => self.vec = Arc::new(Vec::new())
+        }
+    }
+
+    /// The elements, copied out first if the buffer is shared.
+    pub(crate) fn into_vec(mut self) -> Vec<T> {
+        core::mem::take(&mut *self)
+    }
+}
+
+impl<T> From<Vec<T>> for SharedVec<T> {
+    fn from(vec: Vec<T>) -> SharedVec<T> {
+        SharedVec {
+            vec: Arc::new(vec),
+            make_mut: None
+        }
+    }
+}
+
+impl<T> Deref for SharedVec<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.vec
+    }
+}
+
+impl<T> DerefMut for SharedVec<T> {
+    fn deref_mut(&mut self) -> &mut Vec<T> {
+        match self.make_mut {
+            Some(make_mut) => make_mut(&mut self.vec),
+            None => Arc::get_mut(&mut self.vec).expect("buffer shared without a snapshot")
+        }
+    }
+}
+
+impl<T: Clone> Clone for SharedVec<T> {
+    fn clone(&self) -> SharedVec<T> {
+        Vec::clone(&self.vec).into()
+    }
+}
+
+impl<T: PartialEq> PartialEq for SharedVec<T> {
+    fn eq(&self, other: &SharedVec<T>) -> bool {
+        self.vec == other.vec
+    }
+}
+
+impl<T: Eq> Eq for SharedVec<T> {}
+
+impl<T: fmt::Debug> fmt::Debug for SharedVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.vec.fmt(f)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for SharedVec<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.vec.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for SharedVec<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<SharedVec<T>, D::Error> {
+        Vec::deserialize(deserializer).map(SharedVec::from)
+    }
+}
+
+impl<N: Eq + Clone, L: Clone> FlatTrie<N, L> {
+    /// Takes a read-only snapshot of the trie, without copying it.
+    ///
+    /// The snapshot shares the trie's buffers. The next change to a buffer copies it, so snapshots
+    /// never see later changes, and the trie goes back to changing its buffers in place once every
+    /// snapshot of it is dropped.
+    pub fn snapshot(&mut self) -> Snapshot<N, L> {
+        Snapshot(FlatTrie(self.0.share()))
+    }
+}
+
+impl<N: Eq, L> Snapshot<N, L> {
+    /// A cursor at the root that owns the snapshot, so it can be kept or sent to another thread
+    /// independently of the trie.
+    pub fn cursor_owned(self) -> Cursor<N, L, Snapshot<N, L>> {
+        Cursor {
+            tree: self,
+            raw: RawCursor::root(),
+            _marker: PhantomData
+        }
+    }
+
+    /// Whether both snapshots share the same buffers, which means they were taken from the same
+    /// version of a trie.
+    pub fn ptr_eq(&self, other: &Snapshot<N, L>) -> bool {
+        self.0.0.shares_buffers(&other.0.0)
+    }
+}
+
+impl<N: Eq, L> Clone for Snapshot<N, L> {
+    fn clone(&self) -> Snapshot<N, L> {
+        Snapshot(FlatTrie(self.0.0.reshare()))
+    }
+}
+
+impl<N: Eq, L> Deref for Snapshot<N, L> {
+    type Target = FlatTrie<N, L>;
+
+    fn deref(&self) -> &FlatTrie<N, L> {
+        &self.0
+    }
+}
+
+impl<N: Eq, L> Borrow<FlatTrie<N, L>> for Snapshot<N, L> {
+    fn borrow(&self) -> &FlatTrie<N, L> {
+        &self.0
+    }
+}
+
+impl<N: Eq + fmt::Debug, L: fmt::Debug> fmt::Debug for Snapshot<N, L> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Snapshot").field(&self.0).finish()
+    }
+}
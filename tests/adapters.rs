@@ -0,0 +1,276 @@
+//! Differential tests for the tries built on `FlatTrie`, against a `BTreeMap` keyed by the whole
+//! key.
+
+use flat_trie::*;
+use proptest::prelude::*;
+use proptest::collection::vec;
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone)]
+enum RadixOp {
+    Insert(Vec<u8>, u32),
+    Remove(Vec<u8>),
+    PrunePrefix(Vec<u8>)
+}
+
+fn radix_op() -> impl Strategy<Value=RadixOp> {
+    // Long keys over a tiny alphabet make splits and merges in the middle of nodes common.
+    let key = || vec(0u8..3, 1..8);
+    prop_oneof![
+        4 => (key(), any::<u32>()).prop_map(|(k, l)| RadixOp::Insert(k, l)),
+        2 => key().prop_map(RadixOp::Remove),
+        // The empty key finds the root, which never has a leaf.
+        1 => vec(0u8..3, 0..2).prop_map(RadixOp::Remove),
+        1 => vec(0u8..3, 0..4).prop_map(RadixOp::PrunePrefix)
+    ]
+}
+
+type RadixCursor<'a> = Cursor<Box<[u8]>, u32, &'a FlatTrie<Box<[u8]>, u32>>;
+
+/// Checks that every node other than the root has a leaf or branches, and that no two siblings
+/// start with the same element.
+fn check_radix_shape(cursor: &mut RadixCursor) {
+    let children: Vec<Box<[u8]>> = cursor.direct_children().cloned().collect();
+    if !cursor.at_root() {
+        assert!(!cursor.node().is_empty());
+        assert!(cursor.leaf().is_some() || children.len() >= 2, "{:?} should be merged", cursor.node());
+    }
+    for (i, child) in children.iter().enumerate() {
+        assert!(children[..i].iter().all(|c| c[0] != child[0]));
+        cursor.child(child.clone()).unwrap_occupied().enter();
+        check_radix_shape(cursor);
+        cursor.parent().enter();
+    }
+}
+
+proptest! {
+    #[test]
+    fn radix_matches_model(ops in vec(radix_op(), 0..64)) {
+        let mut trie = RadixTrie::new();
+        let mut model = BTreeMap::new();
+        for op in &ops {
+            match *op {
+                RadixOp::Insert(ref key, leaf) => {
+                    prop_assert_eq!(trie.insert(key, leaf), model.insert(key.clone(), leaf));
+                },
+                RadixOp::Remove(ref key) => {
+                    prop_assert_eq!(trie.remove(key), model.remove(key));
+                },
+                RadixOp::PrunePrefix(ref prefix) => {
+                    let before = model.len();
+                    model.retain(|k, _| !k.starts_with(prefix));
+                    prop_assert_eq!(trie.prune_prefix(prefix), before - model.len());
+                }
+            }
+
+            trie.as_flat().validate().unwrap();
+            check_radix_shape(&mut trie.as_flat().cursor());
+            // Edits are made in place, and should leave the jumps just as rebuilding them would.
+            let mut rebuilt = trie.as_flat().clone();
+            rebuilt.batch(|b| b.insert(None, None));
+            prop_assert_eq!(format!("{:?}", rebuilt), format!("{:?}", trie.as_flat()));
+            prop_assert_eq!(trie.len(), model.len());
+            prop_assert!(trie.node_count() < 2 * model.len().max(1));
+            let mut iterated: Vec<(Vec<u8>, u32)> = trie.iter().map(|(k, &l)| (k, l)).collect();
+            iterated.sort();
+            prop_assert_eq!(iterated, model.iter().map(|(k, &l)| (k.clone(), l)).collect::<Vec<_>>());
+            for key in model.keys() {
+                prop_assert_eq!(trie.get(key), model.get(key));
+                prop_assert!(trie.contains_prefix(&key[..key.len() / 2]));
+            }
+        }
+    }
+}
+
+fn str_key() -> impl Strategy<Value=String> {
+    // Includes a multi-byte character, so keys' byte and `char` lengths differ.
+    vec(prop_oneof![Just('a'), Just('b'), Just('é')], 0..6).prop_map(|chars| chars.into_iter().collect())
+}
+
+proptest! {
+    #[test]
+    fn str_trie_matches_model(ops in vec((str_key(), prop::option::of(any::<u32>())), 0..48), prefix in str_key()) {
+        let mut trie = StrTrie::new();
+        let mut bytes = BytesTrie::new();
+        let mut model = BTreeMap::new();
+        for (key, leaf) in &ops {
+            if key.is_empty() {
+                continue;
+            }
+            match *leaf {
+                Some(leaf) => {
+                    prop_assert_eq!(trie.insert(key, leaf), model.insert(key.clone(), leaf));
+                    bytes.insert(key.as_bytes(), leaf);
+                },
+                None => {
+                    prop_assert_eq!(trie.remove(key), model.remove(key));
+                    bytes.remove(key.as_bytes());
+                }
+            }
+            trie.as_flat().validate().unwrap();
+            let mut rebuilt = trie.as_flat().clone();
+            rebuilt.batch(|b| b.insert(None, None));
+            prop_assert_eq!(format!("{:?}", rebuilt), format!("{:?}", trie.as_flat()));
+            // Removing a key shouldn't leave behind nodes that no key goes through.
+            prop_assert_eq!(trie.as_flat().node_count(), {
+                let prefixes: std::collections::BTreeSet<String> = model.keys()
+                    .flat_map(|k| k.char_indices().map(move |(i, c)| k[..i + c.len_utf8()].to_string()))
+                    .collect();
+                prefixes.len()
+            });
+        }
+
+        let mut keys: Vec<String> = trie.keys().collect();
+        keys.sort();
+        prop_assert_eq!(keys, model.keys().cloned().collect::<Vec<_>>());
+        for (key, leaf) in &model {
+            prop_assert_eq!(trie.get(key), Some(leaf));
+            prop_assert_eq!(bytes.get(key.as_bytes()), Some(leaf));
+        }
+
+        let mut prefixed: Vec<(String, u32)> = trie.iter_prefix(&prefix).map(|(k, &l)| (k, l)).collect();
+        prefixed.sort();
+        let expected: Vec<(String, u32)> = model.iter()
+            .filter(|(k, _)| k.starts_with(&prefix))
+            .map(|(k, &l)| (k.clone(), l))
+            .collect();
+        prop_assert_eq!(trie.starts_with(&prefix), !expected.is_empty());
+        prop_assert_eq!(&prefixed, &expected);
+
+        let mut prefixed_bytes: Vec<(Vec<u8>, u32)> = bytes.iter_prefix(prefix.as_bytes()).map(|(k, &l)| (k, l)).collect();
+        prefixed_bytes.sort();
+        prop_assert_eq!(prefixed_bytes, expected.iter().map(|(k, l)| (k.clone().into_bytes(), *l)).collect::<Vec<_>>());
+    }
+}
+
+#[cfg(feature = "std")]
+fn raw_path() -> impl Strategy<Value=String> {
+    let segment = prop_oneof![4 => Just("a"), 4 => Just("b"), 2 => Just(".."), 1 => Just("."), 1 => Just("")];
+    (any::<bool>(), vec(segment, 0..6)).prop_map(|(absolute, segments)| {
+        let path = segments.join("/");
+        match absolute {
+            true => format!("/{}", path),
+            false => path
+        }
+    })
+}
+
+/// Applies `.` and `..` to `path` by hand, for comparison with `PathTrie`.
+#[cfg(feature = "std")]
+fn normalize_by_hand(path: &str) -> Vec<String> {
+    let mut segments = vec![];
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => (),
+            ".." => {segments.pop();},
+            segment => segments.push(segment.to_string())
+        }
+    }
+    segments
+}
+
+#[cfg(feature = "std")]
+proptest! {
+    #[test]
+    fn path_trie_matches_model(ops in vec((raw_path(), prop::option::of(any::<u32>())), 0..48), probes in vec(raw_path(), 0..16)) {
+        use std::path::PathBuf;
+
+        let mut trie = PathTrie::new();
+        let mut model = BTreeMap::new();
+        for (path, leaf) in &ops {
+            let key = normalize_by_hand(path);
+            if key.is_empty() {
+                continue;
+            }
+            match *leaf {
+                Some(leaf) => prop_assert_eq!(trie.insert(path, leaf), model.insert(key, leaf)),
+                None => prop_assert_eq!(trie.remove(path), model.remove(&key))
+            }
+            trie.as_flat().validate().unwrap();
+        }
+
+        let mut iterated: Vec<(PathBuf, u32)> = trie.iter().map(|(p, &l)| (p, l)).collect();
+        iterated.sort();
+        let mut expected: Vec<(PathBuf, u32)> = model.iter().map(|(k, &l)| (k.iter().collect(), l)).collect();
+        expected.sort();
+        prop_assert_eq!(iterated, expected);
+
+        for probe in &probes {
+            let key = normalize_by_hand(probe);
+            prop_assert_eq!(trie.get(probe), model.get(&key));
+            prop_assert_eq!(trie.get(std::path::Path::new(probe)), model.get(&key));
+            let mut prefixed: Vec<PathBuf> = trie.iter_prefix(probe).map(|(p, _)| p).collect();
+            prefixed.sort();
+            let mut expected: Vec<PathBuf> = model.keys().filter(|k| k.starts_with(&key)).map(|k| k.iter().collect()).collect();
+            expected.sort();
+            prop_assert_eq!(prefixed, expected);
+        }
+    }
+}
+
+fn route_segments(choices: &'static [&'static str]) -> impl Strategy<Value=Vec<&'static str>> {
+    vec(prop::sample::select(choices), 0..4)
+}
+
+proptest! {
+    #[test]
+    fn router_matches_model(routes in vec((route_segments(&["a", "b", ":x"]), any::<u32>()), 0..24),
+                            paths in vec(route_segments(&["a", "b", "c"]), 0..16)) {
+        let mut router = Router::new();
+        let mut model = BTreeMap::new();
+        for (route, leaf) in &routes {
+            if route.is_empty() {
+                continue;
+            }
+            let joined = format!("/{}", route.join("/"));
+            prop_assert_eq!(router.insert(&joined, *leaf), model.insert(route.clone(), *leaf));
+        }
+        prop_assert_eq!(router.len(), model.len());
+
+        // `child_by` walks the same segments the router inserted.
+        for (route, leaf) in &model {
+            let mut cursor = router.as_flat().cursor();
+            for &segment in route {
+                let expected = match segment.strip_prefix(':') {
+                    Some(name) => RouteSegment::Param(name.into()),
+                    None => RouteSegment::Literal(segment.into())
+                };
+                cursor.child_by(|node| *node == expected).unwrap_occupied().enter();
+            }
+            prop_assert_eq!(cursor.leaf(), Some(leaf));
+        }
+
+        for path in &paths {
+            // The matching route that prefers a literal at the first position where they differ.
+            let expected = model.iter()
+                .filter(|(route, _)| route.len() == path.len() && route.iter().zip(path).all(|(r, p)| r == p || r.starts_with(':')))
+                .min_by_key(|(route, _)| route.iter().map(|r| r.starts_with(':')).collect::<Vec<_>>());
+            let joined = path.join("/");
+            let found = router.at(&joined);
+            match expected {
+                Some((route, leaf)) => {
+                    let (found_leaf, params) = found.unwrap();
+                    prop_assert_eq!(found_leaf, leaf);
+                    let captures: Vec<(&str, &str)> = route.iter().zip(path).filter(|(r, _)| r.starts_with(':')).map(|(_, &p)| ("x", p)).collect();
+                    prop_assert_eq!(params.iter().collect::<Vec<_>>(), captures);
+                },
+                None => prop_assert!(found.is_none())
+            }
+        }
+
+        for route in model.keys().cloned().collect::<Vec<_>>() {
+            prop_assert_eq!(router.remove(&route.join("/")), model.remove(&route));
+            router.as_flat().validate().unwrap();
+        }
+        prop_assert_eq!(router.as_flat().node_count(), 0);
+    }
+}
+
+#[test]
+#[should_panic(expected = "names parameter `:name`")]
+fn router_rejects_conflicting_params() {
+    let mut router = Router::new();
+    router.insert("/users/:id", 1);
+    router.insert("/users/:name/posts", 2);
+}
@@ -0,0 +1,945 @@
+//! Differential tests that apply the same operations to a `FlatTrie` and to a `BTreeMap` model,
+//! and check that every observable result agrees after each step.
+
+use flat_trie::*;
+use proptest::prelude::*;
+use proptest::collection::vec;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+type Path = Vec<u8>;
+
+#[derive(Debug, Clone)]
+enum Op {
+    /// Inserts any missing nodes along the path. The last node gets the leaf if it's newly
+    /// inserted, or has its leaf replaced if it already has one.
+    Insert(Path, u32),
+    /// Replaces the leaf at the end of the path, if there is one.
+    SetLeaf(Path, u32),
+    Prune(Path),
+    /// Removes the leaf at the end of the path, and any ancestors left without leaves or other
+    /// children.
+    Remove(Path),
+    Compact,
+    ShrinkToFit,
+    Clear
+}
+
+/// Every node in the trie, and the leaves of the nodes that have them.
+#[derive(Debug, Default)]
+struct Model {
+    nodes: BTreeSet<Path>,
+    leaves: BTreeMap<Path, u32>
+}
+
+fn path() -> impl Strategy<Value=Path> {
+    // A small alphabet and short paths make shared prefixes, splits and collisions common.
+    vec(0u8..4, 0..6)
+}
+
+fn op() -> impl Strategy<Value=Op> {
+    prop_oneof![
+        3 => (path(), any::<u32>()).prop_map(|(p, l)| Op::Insert(p, l)),
+        1 => (path(), any::<u32>()).prop_map(|(p, l)| Op::SetLeaf(p, l)),
+        2 => path().prop_map(Op::Prune),
+        2 => path().prop_map(Op::Remove),
+        1 => Just(Op::Compact),
+        1 => Just(Op::ShrinkToFit),
+        1 => Just(Op::Clear)
+    ]
+}
+
+impl Model {
+    fn apply(&mut self, op: &Op) {
+        match *op {
+            Op::Insert(ref path, leaf) => {
+                if path.is_empty() {
+                    return;
+                }
+                for i in 1..path.len() {
+                    self.nodes.insert(path[..i].to_vec());
+                }
+                let is_new = self.nodes.insert(path.clone());
+                if is_new || self.leaves.contains_key(path) {
+                    self.leaves.insert(path.clone(), leaf);
+                }
+            },
+            Op::SetLeaf(ref path, leaf) => {
+                if let Some(l) = self.leaves.get_mut(path) {
+                    *l = leaf;
+                }
+            },
+            Op::Prune(ref path) => {
+                if path.is_empty() {
+                    self.nodes.clear();
+                    self.leaves.clear();
+                } else if self.nodes.contains(path) {
+                    self.nodes.retain(|p| !p.starts_with(path));
+                    self.leaves.retain(|p, _| !p.starts_with(path));
+                }
+            },
+            Op::Remove(ref path) => {
+                if self.leaves.remove(path).is_none() {
+                    return;
+                }
+                let mut path = path.clone();
+                while !path.is_empty() && !self.leaves.contains_key(&path) {
+                    let has_children = self.nodes.iter().any(|p| p.len() > path.len() && p.starts_with(&path));
+                    if has_children {
+                        break;
+                    }
+                    self.nodes.remove(&path);
+                    path.pop();
+                }
+            },
+            Op::Compact => {
+                let leaves = &self.leaves;
+                self.nodes.retain(|node| leaves.keys().any(|p| p.starts_with(node)));
+            },
+            Op::ShrinkToFit => (),
+            Op::Clear => {
+                self.nodes.clear();
+                self.leaves.clear();
+            }
+        }
+    }
+
+    fn height(&self) -> usize {
+        self.nodes.iter().map(|p| p.len()).max().unwrap_or(0)
+    }
+}
+
+fn apply(trie: &mut FlatTrie<u8, u32>, op: &Op) {
+    match *op {
+        Op::Insert(ref path, leaf) => {
+            let mut cursor = trie.cursor_mut();
+            for (i, &node) in path.iter().enumerate() {
+                let is_last = i + 1 == path.len();
+                let mut entry = cursor.child(node).or_insert(match is_last {
+                    true => Some(leaf),
+                    false => None
+                });
+                if is_last {
+                    if let Some(l) = entry.leaf_mut() {
+                        *l = leaf;
+                    }
+                }
+                entry.enter();
+            }
+        },
+        Op::SetLeaf(ref path, leaf) => {
+            let mut cursor = trie.cursor_mut();
+            if let Some((&last, init)) = path.split_last() {
+                for &node in init {
+                    match cursor.child(node) {
+                        Entry::Occupied(occupied) => {occupied.enter();},
+                        Entry::Vacant(..) => return
+                    }
+                }
+                if let Entry::Occupied(mut occupied) = cursor.child(last) {
+                    if let Some(l) = occupied.leaf_mut() {
+                        *l = leaf;
+                    }
+                }
+            }
+        },
+        Op::Prune(ref path) => {
+            let mut cursor = trie.cursor_mut();
+            if path.is_empty() {
+                prune_all(&mut cursor);
+                return;
+            }
+            let (&last, init) = path.split_last().unwrap();
+            for &node in init {
+                match cursor.child(node) {
+                    Entry::Occupied(occupied) => {occupied.enter();},
+                    Entry::Vacant(..) => return
+                }
+            }
+            if let Entry::Occupied(mut occupied) = cursor.child(last) {
+                occupied.prune();
+            }
+        },
+        Op::Remove(ref path) => {
+            let expected = trie.get(path).cloned();
+            assert_eq!(trie.remove(path), expected);
+        },
+        Op::Compact => trie.compact(),
+        Op::ShrinkToFit => trie.shrink_to_fit(),
+        Op::Clear => {
+            let capacity = trie.memory_usage().total();
+            trie.clear();
+            assert_eq!(trie.memory_usage().total(), capacity);
+        }
+    }
+}
+
+/// Prunes every top-level node, which is the only way to clear the trie through a cursor.
+fn prune_all(cursor: &mut Cursor<u8, u32, &mut FlatTrie<u8, u32>>) {
+    let children: Vec<u8> = cursor.direct_children().cloned().collect();
+    for child in children {
+        cursor.child(child).unwrap_occupied().prune();
+    }
+}
+
+/// Walks the trie through `Cursor` and lists every node's path, depth and leaf.
+fn collect(trie: &FlatTrie<u8, u32>) -> Vec<(Path, isize, Option<u32>)> {
+    fn walk(cursor: &mut Cursor<u8, u32, &FlatTrie<u8, u32>>, path: &mut Path, out: &mut Vec<(Path, isize, Option<u32>)>) {
+        let children: Vec<u8> = cursor.direct_children().cloned().collect();
+        for child in children {
+            cursor.child(child).unwrap_occupied().enter();
+            path.push(child);
+            out.push((path.clone(), cursor.depth(), cursor.leaf().cloned()));
+            walk(cursor, path, out);
+            path.pop();
+            cursor.parent().enter();
+        }
+    }
+
+    let mut out = vec![];
+    walk(&mut trie.cursor(), &mut vec![], &mut out);
+    out
+}
+
+fn check(trie: &FlatTrie<u8, u32>, model: &Model) {
+    trie.validate().unwrap();
+
+    assert_eq!(trie.len(), model.leaves.len());
+    assert_eq!(trie.node_count(), model.nodes.len());
+    assert_eq!(trie.is_empty(), model.leaves.is_empty());
+    assert_eq!(trie.height(), model.height());
+    let memory = trie.memory_usage();
+    assert!(memory.used <= memory.total());
+
+    // Edits are made in place, and should leave the jumps just as rebuilding them would.
+    let mut rebuilt = trie.clone();
+    rebuilt.batch(|batch| batch.insert(None, None));
+    assert_eq!(trie.to_binary(), rebuilt.to_binary());
+
+    let mut nodes = collect(trie);
+    nodes.sort();
+    let expected: Vec<_> = model.nodes.iter()
+        .map(|p| (p.clone(), p.len() as isize - 1, model.leaves.get(p).cloned()))
+        .collect();
+    assert_eq!(nodes, expected);
+
+    for path in &model.nodes {
+        assert_eq!(trie.get(path), model.leaves.get(path));
+
+        let mut cursor = trie.cursor();
+        for &node in path {
+            cursor.child(node).unwrap_occupied().enter();
+        }
+        let descendants = model.nodes.iter().filter(|p| p.starts_with(path));
+        assert_eq!(cursor.subtree_node_count(), descendants.count());
+        let descendant_leaves = model.leaves.keys().filter(|p| p.starts_with(path));
+        assert_eq!(cursor.subtree_len(), descendant_leaves.count());
+
+        // Walking back up should end at the root.
+        for _ in 0..path.len() {
+            cursor.parent().enter();
+        }
+        assert!(cursor.at_root());
+    }
+    assert_eq!(trie.cursor().subtree_node_count(), model.nodes.len());
+    assert_eq!(trie.cursor().subtree_len(), model.leaves.len());
+
+    // Nodes are stored in pre-order, so `find_nodes` should find them in the order `collect` does.
+    let preorder = collect(trie);
+    for node in 0..4 {
+        let found: Vec<_> = trie.find_nodes(|&n| n == node).map(|c| (*c.node(), c.depth(), c.leaf().cloned())).collect();
+        let expected: Vec<_> = preorder.iter().filter(|(p, _, _)| p.last() == Some(&node)).map(|&(_, depth, leaf)| (node, depth, leaf)).collect();
+        assert_eq!(found, expected);
+        assert_eq!(found.len(), model.nodes.iter().filter(|p| p.last() == Some(&node)).count());
+    }
+
+    for (path, &leaf) in &model.leaves {
+        let mut cursor = trie.cursor();
+        let entry = cursor.find_leaf_after_wrapping(leaf).unwrap();
+        assert_eq!(entry.leaf(), Some(&leaf));
+        assert_eq!(trie.get(path), Some(&leaf));
+    }
+}
+
+proptest! {
+    #[test]
+    fn matches_model(ops in vec(op(), 0..48)) {
+        let mut trie = FlatTrie::new();
+        let mut model = Model::default();
+        for op in &ops {
+            apply(&mut trie, op);
+            model.apply(op);
+            check(&trie, &model);
+        }
+    }
+
+    #[test]
+    fn cursor_survives_sibling_inserts(paths in vec(vec(0u8..4, 1..4), 1..16)) {
+        // Inserting through `child` shouldn't invalidate the cursor the entry came from.
+        let mut trie = FlatTrie::new();
+        {
+            let mut cursor = trie.cursor_mut();
+            cursor.child(9).or_insert(Some(0)).enter();
+            for path in &paths {
+                for &node in path {
+                    let _ = cursor.child(node).or_insert(Some(1)).cont();
+                }
+                prop_assert_eq!(*cursor.node(), 9);
+                prop_assert_eq!(cursor.leaf(), Some(&0));
+            }
+        }
+        trie.validate().unwrap();
+    }
+
+    #[test]
+    fn batch_matches_model(edits in vec((path(), prop::option::of(any::<u32>()), any::<bool>()), 0..48)) {
+        // A batched insert always sets the leaf, unlike `Op::Insert`, so the model is built here.
+        let mut model = Model::default();
+        let mut one_by_one = FlatTrie::new();
+        for &(ref path, leaf, prune) in &edits {
+            match prune {
+                true => model.apply(&Op::Prune(path.clone())),
+                false => {
+                    model.nodes.extend((1..=path.len()).map(|i| path[..i].to_vec()));
+                    if let (false, Some(leaf)) = (path.is_empty(), leaf) {
+                        model.leaves.insert(path.clone(), leaf);
+                    }
+                }
+            }
+            one_by_one.batch(|b| batch_edit(b, path, leaf, prune));
+        }
+
+        let mut trie = FlatTrie::new();
+        trie.batch(|b| for &(ref path, leaf, prune) in &edits {
+            batch_edit(b, path, leaf, prune);
+        });
+        check(&trie, &model);
+        prop_assert_eq!(collect(&trie), collect(&one_by_one));
+    }
+
+    #[test]
+    fn snapshot_is_unaffected(before in vec(op(), 0..24), after in vec(op(), 0..24)) {
+        let mut trie = FlatTrie::new();
+        let mut model = Model::default();
+        for op in &before {
+            apply(&mut trie, op);
+            model.apply(op);
+        }
+        let snapshot = trie.snapshot();
+        let expected = collect(&snapshot);
+
+        let mut model_after = Model {
+            nodes: model.nodes.clone(),
+            leaves: model.leaves.clone()
+        };
+        for op in &after {
+            // `clear` can't keep capacity that a snapshot shares, so prune everything instead.
+            let op = match *op {
+                Op::Clear => &Op::Prune(vec![]),
+                ref op => op
+            };
+            apply(&mut trie, op);
+            model_after.apply(op);
+        }
+        check(&snapshot, &model);
+        check(&trie, &model_after);
+        prop_assert_eq!(collect(&snapshot.clone()), expected);
+
+        // An owned cursor keeps the snapshot alive on another thread.
+        let cursor = snapshot.cursor_owned();
+        let len = std::thread::spawn(move || cursor.subtree_len()).join().unwrap();
+        prop_assert_eq!(len, model.leaves.len());
+    }
+
+    #[test]
+    fn maps_match_model(ops in vec(op(), 0..48)) {
+        let mut trie = FlatTrie::new();
+        let mut model = Model::default();
+        for op in &ops {
+            apply(&mut trie, op);
+            model.apply(op);
+        }
+
+        let expected: Vec<_> = collect(&trie).into_iter()
+            .map(|(path, depth, leaf)| (path.iter().map(|n| n + 4).collect::<Path>(), depth, leaf.map(|l| l / 2)))
+            .collect();
+        let mapped = trie.clone().map(|&n| n + 4, |l| l / 2);
+        prop_assert_eq!(collect(&mapped), expected);
+
+        let failed = trie.clone().try_map(|&n| Ok(n), |l| if l % 3 == 0 { Err(l) } else { Ok(l) });
+        match model.leaves.values().any(|l| l % 3 == 0) {
+            true => prop_assert!(failed.is_err()),
+            false => prop_assert_eq!(collect(&failed.unwrap()), collect(&trie))
+        }
+
+        // Removing the odd leaves should also remove every node without an even leaf below it.
+        let filtered = trie.filter_map_leaves(|l| Some(l).filter(|l| l % 2 == 0));
+        model.leaves.retain(|_, l| *l % 2 == 0);
+        let leaves = &model.leaves;
+        model.nodes.retain(|node| leaves.keys().any(|p| p.starts_with(node)));
+        check(&filtered, &model);
+    }
+
+    #[test]
+    fn retain_matches_model(ops in vec(op(), 0..48)) {
+        let mut trie = FlatTrie::new();
+        let mut model = Model::default();
+        for op in &ops {
+            apply(&mut trie, op);
+            model.apply(op);
+        }
+
+        let rejects = |path: &[u8], leaf: Option<&u32>| {
+            (path.last() == Some(&1) && path.len() % 2 == 1) || leaf.is_some_and(|l| l % 5 == 0)
+        };
+        let mut visited = vec![];
+        trie.retain(|ancestors, &node, leaf| {
+            let mut path: Path = ancestors.iter().map(|&&n| n).collect();
+            path.push(node);
+            assert_eq!(leaf, model.leaves.get(&path));
+            let keep = !rejects(&path, leaf);
+            visited.push(path);
+            keep
+        });
+
+        let removed: Vec<Path> = model.nodes.iter()
+            .filter(|&p| rejects(p, model.leaves.get(p)))
+            .cloned()
+            .collect();
+        model.nodes.retain(|p| !removed.iter().any(|r| p.starts_with(r)));
+        model.leaves.retain(|p, _| !removed.iter().any(|r| p.starts_with(r)));
+        // Every kept node and the root of every removed subtree is visited once.
+        let mut expected: Vec<Path> = model.nodes.iter()
+            .chain(removed.iter().filter(|&r| !removed.iter().any(|q| q != r && r.starts_with(q))))
+            .cloned()
+            .collect();
+        expected.sort();
+        visited.sort();
+        prop_assert_eq!(visited, expected);
+        check(&trie, &model);
+
+        trie.retain_leaves(|l| l % 3 != 0);
+        model.leaves.retain(|_, l| *l % 3 != 0);
+        check(&trie, &model);
+    }
+}
+
+fn segment() -> impl Strategy<Value=Segment<u8>> {
+    prop_oneof![
+        3 => (0u8..4).prop_map(Segment::Exact),
+        1 => Just(Segment::Any),
+        1 => Just(Segment::AnyDepth)
+    ]
+}
+
+/// Whether `path` matches `pattern`, by trying every way `AnyDepth` can split it.
+fn matches_by_hand(pattern: &[Segment<u8>], path: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((Segment::AnyDepth, rest)) => (0..=path.len()).any(|i| matches_by_hand(rest, &path[i..])),
+        Some((segment, rest)) => match path.split_first() {
+            Some((node, path)) => (*segment == Segment::Any || *segment == Segment::Exact(*node)) && matches_by_hand(rest, path),
+            None => false
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn glob_matches_model(ops in vec(op(), 0..48), pattern in vec(segment(), 0..5)) {
+        let mut trie = FlatTrie::new();
+        let mut model = Model::default();
+        for op in &ops {
+            apply(&mut trie, op);
+            model.apply(op);
+        }
+
+        let matched: Vec<(Path, u32)> = trie.matches(&pattern).map(|(path, &l)| (path.into_iter().cloned().collect(), l)).collect();
+        // `collect` walks the trie in pre-order too.
+        let expected: Vec<(Path, u32)> = collect(&trie).into_iter()
+            .filter_map(|(path, _, leaf)| leaf.filter(|_| matches_by_hand(&pattern, &path)).map(|l| (path, l)))
+            .collect();
+        prop_assert_eq!(matched, expected);
+    }
+}
+
+fn levenshtein(a: &[u8], b: &[u8]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (diagonal + usize::from(x != y)).min(above + 1).min(row[j] + 1);
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+proptest! {
+    #[test]
+    fn fuzzy_search_matches_model(ops in vec(op(), 0..48), key in path(), max_distance in 0usize..3) {
+        let mut trie = FlatTrie::new();
+        let mut model = Model::default();
+        for op in &ops {
+            apply(&mut trie, op);
+            model.apply(op);
+        }
+
+        let found: Vec<(Path, u32, usize)> = trie.fuzzy_search(&key, max_distance)
+            .map(|(path, &l, distance)| (path.into_iter().cloned().collect(), l, distance))
+            .collect();
+        let expected: Vec<(Path, u32, usize)> = collect(&trie).into_iter()
+            .filter_map(|(path, _, leaf)| {
+                let distance = levenshtein(&path, &key);
+                leaf.filter(|_| distance <= max_distance).map(|l| (path, l, distance))
+            })
+            .collect();
+        prop_assert_eq!(found, expected);
+    }
+}
+
+proptest! {
+    #[test]
+    fn scan_matches_model(ops in vec(op(), 0..48), text in vec(0u8..4, 0..32)) {
+        let mut trie = FlatTrie::new();
+        let mut model = Model::default();
+        for op in &ops {
+            apply(&mut trie, op);
+            model.apply(op);
+        }
+
+        let found: Vec<(usize, u32)> = trie.matcher().scan(&text).map(|(start, &l)| (start, l)).collect();
+        // Ordered by where they end, then longest first.
+        let text = &text;
+        let mut expected: Vec<(usize, usize, u32)> = (0..text.len())
+            .flat_map(|start| model.leaves.iter()
+                .filter(move |(path, _)| text[start..].starts_with(path))
+                .map(move |(path, &l)| (start + path.len(), start, l)))
+            .collect();
+        expected.sort();
+        prop_assert_eq!(found, expected.into_iter().map(|(_, start, l)| (start, l)).collect::<Vec<_>>());
+    }
+}
+
+fn batch_edit(batch: &mut Batch<u8, u32>, path: &Path, leaf: Option<u32>, prune: bool) {
+    match prune {
+        true => batch.prune(path.iter().cloned()),
+        false => batch.insert(path.iter().cloned(), leaf.filter(|_| !path.is_empty()))
+    }
+}
+
+#[test]
+fn insert_after_leaf_chain() {
+    let mut trie = FlatTrie::new();
+    let mut model = Model::default();
+    let ops = [Op::Insert(vec![2, 2, 1], 0), Op::Insert(vec![0, 2], 1), Op::Insert(vec![0], 2)];
+    for op in &ops {
+        apply(&mut trie, op);
+        model.apply(op);
+        check(&trie, &model);
+    }
+}
+
+#[test]
+fn prune_middle_of_chain() {
+    let mut trie = FlatTrie::new();
+    let mut model = Model::default();
+    let ops = [
+        Op::Insert(vec![0, 1, 2, 3], 0),
+        Op::Insert(vec![0, 1, 3], 1),
+        Op::Insert(vec![1], 2),
+        Op::Prune(vec![0, 1]),
+        Op::Insert(vec![0, 1, 2], 3)
+    ];
+    for op in &ops {
+        apply(&mut trie, op);
+        model.apply(op);
+        check(&trie, &model);
+    }
+}
+
+#[test]
+fn subtree_of_only_child_jump() {
+    // The jump for `[1]` has one child jump, which is followed by the jumps below `[3]`.
+    let mut trie = FlatTrie::new();
+    let mut model = Model::default();
+    let ops = [
+        Op::Insert(vec![1], 0),
+        Op::Insert(vec![1, 2], 1),
+        Op::Insert(vec![3, 4], 2),
+        Op::Insert(vec![3, 5], 3),
+        Op::Prune(vec![1])
+    ];
+    for op in &ops {
+        apply(&mut trie, op);
+        model.apply(op);
+        check(&trie, &model);
+    }
+}
+
+#[test]
+fn reserved_inserts_do_not_reallocate() {
+    fn buffers(trie: &FlatTrie<u8, u32>) -> (usize, usize, usize) {
+        let memory = trie.memory_usage();
+        (memory.nodes, memory.jumps, memory.leaves)
+    }
+
+    // Each path gets three nodes, a jump and a leaf of its own, plus there's the root's jump.
+    let mut trie = FlatTrie::with_capacity(3 * 64, 65, 64);
+    let reserved = buffers(&trie);
+    for i in 0..64 {
+        apply(&mut trie, &Op::Insert(vec![i, i, i], i as u32));
+        assert_eq!(buffers(&trie), reserved);
+    }
+
+    // Batches rebuild the jumps in the same buffers.
+    trie.reserve(3 * 64, 64, 64);
+    let reserved = buffers(&trie);
+    trie.batch(|b| for i in 64..128 {
+        b.insert(vec![i, i, i], Some(i as u32));
+    });
+    assert_eq!(buffers(&trie), reserved);
+    assert_eq!(trie.len(), 128);
+}
+
+#[test]
+fn find_node_after_wrapping_visits_preorder() {
+    fn path(cursor: &Cursor<u8, u32, &FlatTrie<u8, u32>>) -> Path {
+        let mut cursor = *cursor;
+        let mut path = vec![];
+        while !cursor.at_root() {
+            path.push(*cursor.node());
+            cursor.parent().enter();
+        }
+        path.reverse();
+        path
+    }
+
+    // In pre-order, the nodes are 1, 2, 3, 4, 2, 3.
+    let mut trie = FlatTrie::new();
+    for op in &[Op::Insert(vec![1, 2, 3], 0), Op::Insert(vec![1, 4], 1), Op::Insert(vec![2, 3], 2)] {
+        apply(&mut trie, op);
+    }
+
+    let mut cursor = trie.cursor();
+    let steps: [(u8, Path); 5] = [
+        (3, vec![1, 2, 3]),
+        (3, vec![2, 3]),
+        // Wraps around past the last node.
+        (3, vec![1, 2, 3]),
+        (2, vec![2]),
+        (4, vec![1, 4])
+    ];
+    for (node, expected) in steps.iter() {
+        let entry = cursor.find_node_after_wrapping(*node).unwrap();
+        assert_eq!(entry.node(), node);
+        entry.enter();
+        assert_eq!(&path(&cursor), expected);
+    }
+
+    // The node the cursor is at is found last.
+    assert_eq!(path(cursor.find_node_after_wrapping(4).unwrap().enter()), vec![1, 4]);
+    assert!(cursor.find_node_after_wrapping(9).is_err());
+    assert_eq!(path(&cursor), vec![1, 4]);
+}
+
+#[test]
+#[should_panic(expected = "Mapped two siblings to equal nodes")]
+fn map_rejects_equal_siblings() {
+    let mut trie = FlatTrie::new();
+    for op in &[Op::Insert(vec![1, 2], 0), Op::Insert(vec![1, 3], 1), Op::Insert(vec![5], 2)] {
+        apply(&mut trie, op);
+    }
+    // `[1]` and `[5]` stay apart, but `[1, 2]` and `[1, 3]` collide.
+    trie.map(|&n| n / 2, |l| l);
+}
+
+#[test]
+fn snapshots_share_buffers_until_changed() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CLONES: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Counted(u32);
+
+    impl Clone for Counted {
+        fn clone(&self) -> Counted {
+            CLONES.fetch_add(1, Ordering::Relaxed);
+            Counted(self.0)
+        }
+    }
+
+    let mut trie = FlatTrie::new();
+    trie.batch(|b| for i in 0..10_000 {
+        b.insert(Some(Counted(i)), Some(Counted(i)));
+    });
+    let snapshot = trie.snapshot();
+    let other = snapshot.clone();
+    assert!(snapshot.ptr_eq(&other));
+    assert_eq!(CLONES.load(Ordering::Relaxed), 0);
+
+    // Changing a leaf copies the leaves once, but not the nodes.
+    for i in 0..2 {
+        let mut cursor = trie.cursor_mut();
+        cursor.child(Counted(i)).unwrap_occupied().leaf_mut().unwrap().0 += 1;
+    }
+    assert_eq!(CLONES.load(Ordering::Relaxed), 10_000);
+    assert_eq!(snapshot.get(Some(&Counted(0))), Some(&Counted(0)));
+    assert_eq!(trie.get(Some(&Counted(0))), Some(&Counted(1)));
+
+    // With no snapshots left, the trie changes its buffers in place again.
+    drop((snapshot, other));
+    trie.remove(Some(&Counted(2)));
+    assert_eq!(CLONES.load(Ordering::Relaxed), 10_000);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn concurrent_readers_see_whole_writes() {
+    // Every write adds a batch of ten leaves, so readers should only ever see multiples of ten.
+    let trie = std::sync::Arc::new(ConcurrentFlatTrie::default());
+    let readers: Vec<_> = (0..4).map(|_| {
+        let trie = trie.clone();
+        std::thread::spawn(move || {
+            let mut last = 0;
+            while last < 200 {
+                let snapshot = trie.load();
+                snapshot.validate().unwrap();
+                let len = snapshot.cursor_owned().subtree_len();
+                assert_eq!(len % 10, 0);
+                assert!(len >= last);
+                last = len;
+            }
+        })
+    }).collect();
+
+    for i in 0..20u32 {
+        trie.write(|t| t.batch(|b| for j in 0..10 {
+            b.insert(vec![(i % 4) as u8, i as u8, j], Some(i * 10 + j as u32));
+        }));
+    }
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    let trie = std::sync::Arc::try_unwrap(trie).unwrap().into_inner();
+    assert_eq!(trie.len(), 200);
+}
+
+proptest! {
+    #[test]
+    fn binary_round_trips(ops in vec(op(), 0..48)) {
+        let mut trie = FlatTrie::new();
+        for op in &ops {
+            apply(&mut trie, op);
+        }
+
+        let bytes = trie.to_binary();
+        prop_assert_eq!(bytes.len(), trie.binary_len());
+        let copied = FlatTrie::<u8, u32>::from_binary(&bytes).unwrap();
+        copied.validate().unwrap();
+        prop_assert_eq!(collect(&copied), collect(&trie));
+        prop_assert_eq!(copied.to_binary(), bytes.clone());
+
+        let view = FlatTrieView::<u8, u32>::new(&bytes).unwrap();
+        prop_assert_eq!(view.len(), trie.len());
+        prop_assert_eq!(view.node_count(), trie.node_count());
+        for (path, _, leaf) in collect(&trie) {
+            prop_assert_eq!(view.get(&path), leaf);
+        }
+    }
+}
+
+#[test]
+fn binary_rejects_invalid_data() {
+    let mut trie = FlatTrie::<u8, u32>::new();
+    for op in &[Op::Insert(vec![1, 2, 3], 0), Op::Insert(vec![1, 4], 1)] {
+        apply(&mut trie, op);
+    }
+    let bytes = trie.to_binary();
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = b'G';
+    assert_eq!(FlatTrieView::<u8, u32>::new(&bad_magic).err(), Some(FormatError::BadMagic));
+    assert_eq!(FlatTrieView::<u8, u32>::new(&bytes[..20]).err(), Some(FormatError::BadMagic));
+    assert_eq!(FlatTrieView::<u8, u32>::new(&bytes[..bytes.len() - 1]).err(), Some(FormatError::LengthMismatch));
+    assert_eq!(FlatTrieView::<u16, u32>::new(&bytes).err(), Some(FormatError::WidthMismatch));
+
+    // The header is 48 bytes, followed by 48-byte jump records. The second record's first node is
+    // at offset 8, and its distance to its major node at offset 16.
+    let record = 48 + 48;
+    let mut bad_node = bytes.clone();
+    bad_node[record + 8..record + 16].copy_from_slice(&(trie.node_count() as i64).to_le_bytes());
+    assert_eq!(FlatTrieView::<u8, u32>::new(&bad_node).err(), Some(FormatError::BadJump(1)));
+
+    let mut overflowing_node = bytes.clone();
+    overflowing_node[record + 8..record + 16].copy_from_slice(&i64::MAX.to_le_bytes());
+    overflowing_node[record + 16..record + 24].copy_from_slice(&1u64.to_le_bytes());
+    assert_eq!(FlatTrieView::<u8, u32>::new(&overflowing_node).err(), Some(FormatError::BadJump(1)));
+    assert_eq!(FlatTrie::<u8, u32>::from_binary(&overflowing_node).err(), Some(FormatError::BadJump(1)));
+
+    // The root's record has a leaf index like any other, but the root can't have a leaf. The leaf
+    // count is the last field of the header, and the leaf index is at offset 32 of a record.
+    let mut root_leaf = FlatTrie::<u8, u32>::new().to_binary();
+    root_leaf[40..48].copy_from_slice(&1u64.to_le_bytes());
+    root_leaf[48 + 32..48 + 40].copy_from_slice(&0i64.to_le_bytes());
+    root_leaf.extend_from_slice(&7u32.to_le_bytes());
+    let error = FormatError::Integrity(IntegrityError::MissingRoot);
+    assert_eq!(FlatTrie::<u8, u32>::from_binary(&root_leaf).err(), Some(error));
+}
+
+#[test]
+fn binary_reports_integrity_errors() {
+    /// A jump record's parent jump index, first node, distance to the major node, depth, leaf
+    /// index and child jump index.
+    type Record = [i64; 6];
+
+    /// Writes the binary form of a trie with the given jump records.
+    fn binary(jumps: &[Record], nodes: &[u8], leaves: &[u32]) -> Vec<u8> {
+        let mut bytes = b"FLATTRIE".to_vec();
+        for field in &[FORMAT_VERSION, 1, 4, 0] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        for len in &[jumps.len(), nodes.len(), leaves.len()] {
+            bytes.extend_from_slice(&(*len as u64).to_le_bytes());
+        }
+        for field in jumps.iter().flatten() {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.extend_from_slice(nodes);
+        for leaf in leaves {
+            bytes.extend_from_slice(&leaf.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn error(jumps: &[Record], nodes: &[u8], leaves: &[u32]) -> Option<IntegrityError> {
+        match FlatTrie::<u8, u32>::from_binary(&binary(jumps, nodes, leaves)) {
+            Ok(_) => None,
+            Err(FormatError::Integrity(e)) => Some(e),
+            Err(e) => panic!("{:?}", e)
+        }
+    }
+
+    // `[1, 2]` and `[3]`, each with a leaf.
+    let root = [-1, -1, 0, -1, -1, 1];
+    let first = [0, 0, 1, 0, 0, -1];
+    let second = [0, 2, 0, 0, 1, -1];
+    assert_eq!(error(&[root, first, second], &[1, 2, 3], &[0, 1]), None);
+
+    use IntegrityError::*;
+    assert_eq!(error(&[[-1, -1, 0, 0, -1, 1], first, second], &[1, 2, 3], &[0, 1]), Some(MissingRoot));
+    assert_eq!(error(&[root, [0, 2, 0, 0, 0, -1], [0, 0, 1, 0, 1, -1]], &[1, 2, 3], &[0, 1]), Some(UnsortedJump(2)));
+    assert_eq!(error(&[root, first, [1, 2, 0, 0, 1, -1]], &[1, 2, 3], &[0, 1]), Some(BadParent(2)));
+    assert_eq!(error(&[root, [0, 0, 1, 0, 0, 2], second], &[1, 2, 3], &[0, 1]), Some(BadChild(1)));
+    assert_eq!(error(&[root, first, [0, 2, 0, 1, 1, -1]], &[1, 2, 3], &[0, 1]), Some(BadDepth(2)));
+    assert_eq!(error(&[root, first, [0, 1, 0, 0, 1, -1]], &[1, 2, 3], &[0, 1]), Some(BadNodeRange(2)));
+    assert_eq!(error(&[root, [0, 0, 1, 0, 1, -1], [0, 2, 0, 0, 0, -1]], &[1, 2, 3], &[0, 1]), Some(BadLeafIndex(1)));
+    assert_eq!(error(&[root, first, second], &[1, 2, 1], &[0, 1]), Some(DuplicateSibling(2)));
+    assert_eq!(error(&[root, first, second], &[1, 2, 3], &[0, 1, 2]), Some(UnusedLeaves));
+    assert_eq!(error(&[root, first, second], &[1, 2, 3, 4], &[0, 1]), Some(UnusedNodes));
+}
+
+#[cfg(feature = "serde")]
+proptest! {
+    #[test]
+    fn serde_round_trips(ops in vec(op(), 0..48)) {
+        let mut trie = FlatTrie::new();
+        for op in &ops {
+            apply(&mut trie, op);
+        }
+
+        let nested_json = serde_json::to_string(&trie).unwrap();
+        let nested: FlatTrie<u8, u32> = serde_json::from_str(&nested_json).unwrap();
+        nested.validate().unwrap();
+        prop_assert_eq!(collect(&nested), collect(&trie));
+
+        let mut compact_json = vec![];
+        compact::serialize(&trie, &mut serde_json::Serializer::new(&mut compact_json)).unwrap();
+        let compact: FlatTrie<u8, u32> = compact::deserialize(&mut serde_json::Deserializer::from_slice(&compact_json)).unwrap();
+        compact.validate().unwrap();
+        prop_assert_eq!(collect(&compact), collect(&trie));
+
+        // Both should decode to the same nodes, jumps and leaves.
+        prop_assert_eq!(nested.to_binary(), compact.to_binary());
+    }
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn serde_rejects_invalid_tries() {
+    let nested = r#"{"1": {"leaf": 0, "children": {}}, "1": {"leaf": 1, "children": {}}}"#;
+    assert!(serde_json::from_str::<FlatTrie<u8, u32>>(nested).is_err());
+
+    // A trie with a leaf but without the node it belongs to.
+    let mut trie = FlatTrie::new();
+    apply(&mut trie, &Op::Insert(vec![1], 0));
+    let mut compact_json = vec![];
+    compact::serialize(&trie, &mut serde_json::Serializer::new(&mut compact_json)).unwrap();
+    let mut value: serde_json::Value = serde_json::from_slice(&compact_json).unwrap();
+    value["nodes"] = serde_json::json!([]);
+    let compact_json = serde_json::to_vec(&value).unwrap();
+    let result: Result<FlatTrie<u8, u32>, _> = compact::deserialize(&mut serde_json::Deserializer::from_slice(&compact_json));
+    assert!(result.is_err());
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn validate_rejects_overflowing_node_range() {
+    let mut trie = FlatTrie::new();
+    for op in &[Op::Insert(vec![1], 0), Op::Insert(vec![2], 1)] {
+        apply(&mut trie, op);
+    }
+    let mut compact_json = vec![];
+    compact::serialize(&trie, &mut serde_json::Serializer::new(&mut compact_json)).unwrap();
+    let mut value: serde_json::Value = serde_json::from_slice(&compact_json).unwrap();
+    value["jumps"][1]["jump_to_node"] = serde_json::json!(isize::MAX);
+    value["jumps"][1]["next_major_node_dist"] = serde_json::json!(1);
+    let compact_json = serde_json::to_vec(&value).unwrap();
+    let result: Result<FlatTrie<u8, u32>, _> = compact::deserialize(&mut serde_json::Deserializer::from_slice(&compact_json));
+    assert_eq!(result.unwrap_err().to_string(), IntegrityError::BadNodeRange(1).to_string());
+}
+
+#[cfg(feature = "rayon")]
+proptest! {
+    #[test]
+    fn par_iter_visits_every_leaf(ops in vec(op(), 0..48)) {
+        use rayon::prelude::*;
+
+        let mut trie = FlatTrie::new();
+        for op in &ops {
+            apply(&mut trie, op);
+        }
+        let mut expected: Vec<(Path, u32)> = collect(&trie).into_iter()
+            .filter_map(|(path, _, leaf)| leaf.map(|l| (path, l)))
+            .collect();
+        expected.sort();
+
+        let mut visited: Vec<(Path, u32)> = trie.par_iter()
+            .map(|(mut cursor, &leaf)| {
+                let mut path = vec![];
+                while !cursor.at_root() {
+                    path.push(*cursor.node());
+                    cursor.parent().enter();
+                }
+                path.reverse();
+                (path, leaf)
+            })
+            .collect();
+        visited.sort();
+        prop_assert_eq!(&visited, &expected);
+
+        let mapped = trie.map_leaves(|&l| l as u64 * 2);
+        mapped.validate().unwrap();
+        for (path, leaf) in &expected {
+            prop_assert_eq!(mapped.get(path), Some(&(*leaf as u64 * 2)));
+        }
+
+        trie.par_leaves_mut().for_each(|l| *l = l.wrapping_add(1));
+        for (path, leaf) in &expected {
+            prop_assert_eq!(trie.get(path), Some(&leaf.wrapping_add(1)));
+        }
+    }
+}